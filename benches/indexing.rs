@@ -0,0 +1,135 @@
+//! Compares the `hash % capacity` slot computation the table used to do with
+//! the fibonacci-mixed mask it uses now, then times whole-table workloads.
+//! Run with `cargo bench --bench indexing`.
+use robinhood_hash_table::rh_hash_table::RobinHoodHashTable;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const FIBONACCI_MULTIPLIER: u64 = 0x9E37_79B9_7F4A_7C15;
+const PROBES: usize = 4;
+
+#[derive(Default)]
+struct IdentityHasher(u64);
+
+impl Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 << 8) | u64::from(*byte);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = i;
+    }
+}
+
+fn report(name: &str, operations: usize, elapsed: Duration) {
+    println!(
+        "{:<44} {:>10.2} ns/op",
+        name,
+        elapsed.as_nanos() as f64 / operations as f64
+    );
+}
+
+/// The old path: modulo for the home slot and a compare-and-reset to wrap.
+fn modulo_probe(hashes: &[u64], capacity: usize) -> usize {
+    let mut sum = 0;
+    for hash in hashes {
+        let mut hash_id = *hash as usize % capacity;
+        for _ in 0..PROBES {
+            sum += hash_id;
+            hash_id += 1;
+            if hash_id >= capacity {
+                hash_id = 0;
+            }
+        }
+    }
+    sum
+}
+
+/// The new path: fibonacci mix, top bits as the home slot, mask to wrap.
+fn mask_probe(hashes: &[u64], capacity: usize) -> usize {
+    let mask = capacity - 1;
+    let bits = capacity.trailing_zeros();
+    let mut sum = 0;
+    for hash in hashes {
+        let mixed = hash.wrapping_mul(FIBONACCI_MULTIPLIER);
+        let mut hash_id = mixed.rotate_left(bits) as usize & mask;
+        for _ in 0..PROBES {
+            sum += hash_id;
+            hash_id = (hash_id + 1) & mask;
+        }
+    }
+    sum
+}
+
+fn bench_indexing() {
+    let capacity = 1 << 20;
+    let state = RandomState::new();
+    let hashes: Vec<u64> = (0..1_000_000u64).map(|i| state.hash_one(i)).collect();
+
+    let start = Instant::now();
+    black_box(modulo_probe(black_box(&hashes), black_box(capacity)));
+    report(
+        "slot index: modulo + branch wrap",
+        hashes.len(),
+        start.elapsed(),
+    );
+
+    let start = Instant::now();
+    black_box(mask_probe(black_box(&hashes), black_box(capacity)));
+    report(
+        "slot index: fibonacci + mask wrap",
+        hashes.len(),
+        start.elapsed(),
+    );
+}
+
+fn bench_table<S: BuildHasher + Clone>(name: &str, state: S, keys: &[u64]) {
+    let mut rht = RobinHoodHashTable::with_capacity_and_hasher(16, state);
+    let start = Instant::now();
+    for key in keys {
+        rht.insert(*key, *key);
+    }
+    report(&format!("{}: insert", name), keys.len(), start.elapsed());
+
+    let start = Instant::now();
+    for key in keys {
+        black_box(rht.get(key));
+    }
+    report(&format!("{}: get hit", name), keys.len(), start.elapsed());
+
+    let start = Instant::now();
+    for key in keys {
+        black_box(rht.contains(&(key + 1)));
+    }
+    report(
+        &format!("{}: contains miss", name),
+        keys.len(),
+        start.elapsed(),
+    );
+}
+
+fn main() {
+    bench_indexing();
+
+    let sequential: Vec<u64> = (0..1_000_000u64).map(|i| i * 2).collect();
+    let strided: Vec<u64> = (0..1_000_000u64).map(|i| i << 12).collect();
+    bench_table("sip, sequential keys", RandomState::new(), &sequential);
+    bench_table(
+        "identity, sequential keys",
+        BuildHasherDefault::<IdentityHasher>::default(),
+        &sequential,
+    );
+    bench_table(
+        "identity, keys strided by 4096",
+        BuildHasherDefault::<IdentityHasher>::default(),
+        &strided,
+    );
+}
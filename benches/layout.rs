@@ -0,0 +1,99 @@
+//! Times lookup-heavy workloads on the interleaved `KeyValuePair` layout and
+//! the struct-of-arrays `SplitKeyValue` layout. With 64-byte values a probe in
+//! the interleaved layout touches a new cache line per slot, while the split
+//! layout only walks the key array.
+//!
+//! `cargo bench --bench layout` times both layouts. To compare their cache
+//! misses, pass one layout so each gets a process of its own and run that
+//! under `perf stat`:
+//!
+//! ```text
+//! cargo bench --bench layout -- interleaved
+//! cargo bench --bench layout -- split
+//! perf stat -e cache-misses,cache-references target/release/deps/layout-<hash> split
+//! ```
+use robinhood_hash_table::rh_hash_table::{
+    KeyValuePair, RobinHoodHashTable, RobinHoodHashTableBuilder, SlotLayout, SplitKeyValue,
+};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ENTRIES: u64 = 1 << 20;
+
+type Payload = [u64; 8];
+
+fn report(name: &str, operations: usize, elapsed: Duration) {
+    println!(
+        "{:<44} {:>10.2} ns/op",
+        name,
+        elapsed.as_nanos() as f64 / operations as f64
+    );
+}
+
+fn bench_layout<L>(name: &str, keys: &[u64])
+where
+    L: SlotLayout<Key = u64, Value = Payload>,
+{
+    let mut rht: RobinHoodHashTable<L> = RobinHoodHashTableBuilder::new()
+        .capacity(16)
+        .build_with_layout()
+        .unwrap();
+    for key in keys {
+        rht.insert(*key, [*key; 8]);
+    }
+
+    let start = Instant::now();
+    for key in keys {
+        black_box(rht.get(key));
+    }
+    report(&format!("{}: get hit", name), keys.len(), start.elapsed());
+
+    let start = Instant::now();
+    for key in keys {
+        black_box(rht.contains(&(key + 1)));
+    }
+    report(
+        &format!("{}: contains miss", name),
+        keys.len(),
+        start.elapsed(),
+    );
+
+    let start = Instant::now();
+    let mut sum = 0;
+    for key in keys {
+        sum += rht.get(key).map_or(0, |value| value[0]);
+    }
+    black_box(sum);
+    report(
+        &format!("{}: get hit + read", name),
+        keys.len(),
+        start.elapsed(),
+    );
+}
+
+fn main() {
+    // Cargo passes flags such as `--bench`; the first other argument, if
+    // any, picks the layout.
+    let layout = std::env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let (interleaved, split) = match layout.as_deref() {
+        None => (true, true),
+        Some("interleaved") => (true, false),
+        Some("split") => (false, true),
+        Some(other) => {
+            eprintln!(
+                "unknown layout {:?}; expected `interleaved` or `split`",
+                other
+            );
+            std::process::exit(2);
+        }
+    };
+
+    // Odd keys only, so `key + 1` is always a miss.
+    let keys: Vec<u64> = (0..ENTRIES).map(|i| i * 2 + 1).collect();
+    if interleaved {
+        bench_layout::<KeyValuePair<u64, Payload>>("interleaved, 64-byte values", &keys);
+    }
+    if split {
+        bench_layout::<SplitKeyValue<u64, Payload>>("split, 64-byte values", &keys);
+    }
+}
@@ -1,212 +1,5397 @@
 pub mod rh_hash_table {
-    use std::collections::hash_map::{DefaultHasher, Keys, RandomState};
-    use std::fmt::Display;
+    use std::borrow::Borrow;
+    use std::collections::hash_map::RandomState;
+    use std::convert::TryInto;
+    use std::error::Error;
+    use std::fmt;
     use std::hash::{BuildHasher, Hash, Hasher};
-    use std::ops::Deref;
+    use std::iter::FromIterator;
+    use std::marker::PhantomData;
+    use std::mem;
+    use std::num::NonZeroUsize;
+    use std::ops::{BitAnd, BitOr, BitXor, Index, Sub};
+    use std::sync::Arc;
 
+    /// A stored key and its value. The PSL and cached hash of its slot live in
+    /// separate arrays. As a table's first type parameter it selects the
+    /// default layout, with each key stored next to its value.
     #[derive(PartialEq, Eq, Copy, Clone)]
     pub struct KeyValuePair<K, V> {
         pub key: K,
         value: V,
-        pub probing_sequence_length: i64,
     }
 
-    impl<K: Hash + Clone + Eq + Copy, V: Clone + Copy> KeyValuePair<K, V> {
-        pub fn new(key: K, value: V, psl: i64) -> Self {
-            Self {
-                key,
-                value,
-                probing_sequence_length: psl,
+    impl<K, V> KeyValuePair<K, V> {
+        pub fn new(key: K, value: V) -> Self {
+            Self { key, value }
+        }
+    }
+
+    /// The error returned by the fallible allocation methods such as
+    /// `RobinHoodHashTable::try_reserve`.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum TryReserveError {
+        /// The requested capacity does not fit in a power-of-two `usize`.
+        CapacityOverflow,
+        /// The allocator could not provide the backing array.
+        AllocError(std::collections::TryReserveError),
+    }
+
+    impl fmt::Display for TryReserveError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                TryReserveError::CapacityOverflow => {
+                    write!(f, "requested table capacity overflows usize")
+                }
+                TryReserveError::AllocError(err) => {
+                    write!(f, "failed to allocate table slots: {}", err)
+                }
             }
         }
     }
-    #[derive(Debug, Clone)]
-    pub struct RobinHoodHashTable<KeyValuePair> {
-        pub capacity: usize,
-        num_entries: i64,
-        max_load_factor: f64,
-        pub table: Vec<Option<KeyValuePair>>,
-        pub hasher_state: RandomState,
+
+    impl Error for TryReserveError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            match self {
+                TryReserveError::CapacityOverflow => None,
+                TryReserveError::AllocError(err) => Some(err),
+            }
+        }
     }
 
-    impl<K: Hash + Display + Clone + Eq, V: Display + Clone + Eq>
-        RobinHoodHashTable<KeyValuePair<K, V>>
-    {
-        /// When we create a new hash table we must define the capacity for later resizing
-        /// Currently we create a hasher using the default SipHash implementation.
-        pub fn new(max_load: f64, capacity: usize) -> Box<Self> {
-            let hasher_state = RandomState::new();
-            Box::new(Self {
-                capacity,
-                num_entries: 0,
-                max_load_factor: max_load,
-                table: vec![None; capacity],
-                hasher_state,
-            })
+    /// Maps a cached hash to its home slot in a table of `capacity` slots, using
+    /// the hash's top bits as the index.
+    fn home_slot(hash: u32, capacity: usize) -> usize {
+        (u64::from(hash) << 32).rotate_left(capacity.trailing_zeros()) as usize & (capacity - 1)
+    }
+
+    /// Capacity is always a power of two, so wrapping around is a mask.
+    fn next_slot(hash_id: usize, capacity: usize) -> usize {
+        (hash_id + 1) & (capacity - 1)
+    }
+
+    /// Picks how a table lays out its slots, as the first type parameter of
+    /// `RobinHoodHashTable`. `KeyValuePair<K, V>` stores each key next to its
+    /// value; `SplitKeyValue<K, V>` keeps keys and values in separate arrays so
+    /// probes, which only compare keys, do not pull values into cache. Either
+    /// way the PSL metadata and cached hashes live in arrays of their own.
+    /// Implemented only by those two types.
+    pub trait SlotLayout {
+        type Key;
+        type Value;
+        #[doc(hidden)]
+        type Columns: layout::Columns<Key = Self::Key, Value = Self::Value>;
+    }
+
+    impl<K, V> SlotLayout for KeyValuePair<K, V> {
+        type Key = K;
+        type Value = V;
+        type Columns = layout::PairColumns<K, V>;
+    }
+
+    /// Selects the struct-of-arrays layout, e.g.
+    /// `RobinHoodHashTable<SplitKeyValue<u64, [u8; 64]>>`. Worth it for
+    /// lookup-heavy tables whose values are large next to their keys. Build one
+    /// with `RobinHoodHashTableBuilder::build_with_layout` or `Default`.
+    pub struct SplitKeyValue<K, V> {
+        _marker: PhantomData<(K, V)>,
+    }
+
+    impl<K, V> SlotLayout for SplitKeyValue<K, V> {
+        type Key = K;
+        type Value = V;
+        type Columns = layout::SplitColumns<K, V>;
+    }
+
+    mod layout {
+        use super::{KeyValuePair, TryReserveError};
+        use std::mem::{self, MaybeUninit};
+        use std::ptr;
+
+        fn try_uninit<T>(capacity: usize) -> Result<Vec<MaybeUninit<T>>, TryReserveError> {
+            let mut column = Vec::new();
+            column
+                .try_reserve_exact(capacity)
+                .map_err(TryReserveError::AllocError)?;
+            column.resize_with(capacity, MaybeUninit::uninit);
+            Ok(column)
         }
 
-        pub fn insert(&mut self, key: K, value: V) {
-            let mut key_value = KeyValuePair {
-                key,
-                value,
-                probing_sequence_length: 0,
-            };
-            let mut hasher = self.hasher_state.build_hasher();
-            key_value.key.hash(&mut hasher);
-            let mut hash_id = hasher.finish() as usize % self.capacity;
-            loop {
-                let mut bucket = self.table[hash_id].clone();
-                match bucket {
-                    Some(..) => {
-                        if bucket.as_ref().unwrap().probing_sequence_length
-                            > key_value.probing_sequence_length
-                        {
-                            let temp = bucket.clone().unwrap();
-                            bucket = Some(key_value.clone());
-                            key_value = temp;
-                        }
+        /// The keys and values of a backing array, one per slot. Which slots
+        /// are initialised is tracked by the owning `Slots`; the unsafe methods
+        /// require slot `index` to be initialised.
+        pub trait Columns: Sized {
+            type Key;
+            type Value;
 
-                        key_value.probing_sequence_length += 1;
-                        hash_id += 1;
-                        if hash_id >= self.capacity {
-                            hash_id = 0;
-                        }
+            fn try_with_capacity(capacity: usize) -> Result<Self, TryReserveError>;
+
+            /// Bytes one slot's key and value take up.
+            fn slot_bytes() -> usize;
+
+            unsafe fn key(&self, index: usize) -> &Self::Key;
+
+            unsafe fn value(&self, index: usize) -> &Self::Value;
+
+            /// Points at the key of slot `index` without creating a reference to
+            /// any other slot, so values already handed out stay valid.
+            fn key_ptr(&self, index: usize) -> *const Self::Key;
+
+            /// Like `key_ptr`, for the value.
+            fn value_ptr(&mut self, index: usize) -> *mut Self::Value;
+
+            /// Moves the entry out; the slot must be treated as uninitialised
+            /// afterwards.
+            unsafe fn read(&mut self, index: usize) -> (Self::Key, Self::Value);
+
+            /// Overwrites slot `index` without dropping what it held.
+            fn write(&mut self, index: usize, key: Self::Key, value: Self::Value);
+
+            /// Swaps the raw contents of two slots.
+            fn swap(&mut self, a: usize, b: usize);
+        }
+
+        pub struct PairColumns<K, V> {
+            entries: Vec<MaybeUninit<KeyValuePair<K, V>>>,
+        }
+
+        impl<K, V> Columns for PairColumns<K, V> {
+            type Key = K;
+            type Value = V;
+
+            fn try_with_capacity(capacity: usize) -> Result<Self, TryReserveError> {
+                Ok(PairColumns {
+                    entries: try_uninit(capacity)?,
+                })
+            }
+
+            fn slot_bytes() -> usize {
+                mem::size_of::<KeyValuePair<K, V>>()
+            }
+
+            unsafe fn key(&self, index: usize) -> &K {
+                &self.entries[index].assume_init_ref().key
+            }
+
+            unsafe fn value(&self, index: usize) -> &V {
+                &self.entries[index].assume_init_ref().value
+            }
+
+            fn key_ptr(&self, index: usize) -> *const K {
+                assert!(index < self.entries.len());
+                let entry = self.entries.as_ptr().wrapping_add(index);
+                // SAFETY: `entry` is in bounds, and taking a field's address
+                // does not read it or create a reference.
+                unsafe { ptr::addr_of!((*entry.cast::<KeyValuePair<K, V>>()).key) }
+            }
+
+            fn value_ptr(&mut self, index: usize) -> *mut V {
+                assert!(index < self.entries.len());
+                let entry = self.entries.as_mut_ptr().wrapping_add(index);
+                // SAFETY: `entry` is in bounds, and taking a field's address
+                // does not read it or create a reference.
+                unsafe { ptr::addr_of_mut!((*entry.cast::<KeyValuePair<K, V>>()).value) }
+            }
+
+            unsafe fn read(&mut self, index: usize) -> (K, V) {
+                let entry = self.entries[index].assume_init_read();
+                (entry.key, entry.value)
+            }
+
+            fn write(&mut self, index: usize, key: K, value: V) {
+                self.entries[index] = MaybeUninit::new(KeyValuePair::new(key, value));
+            }
+
+            fn swap(&mut self, a: usize, b: usize) {
+                self.entries.swap(a, b);
+            }
+        }
+
+        pub struct SplitColumns<K, V> {
+            keys: Vec<MaybeUninit<K>>,
+            values: Vec<MaybeUninit<V>>,
+        }
+
+        impl<K, V> Columns for SplitColumns<K, V> {
+            type Key = K;
+            type Value = V;
+
+            fn try_with_capacity(capacity: usize) -> Result<Self, TryReserveError> {
+                Ok(SplitColumns {
+                    keys: try_uninit(capacity)?,
+                    values: try_uninit(capacity)?,
+                })
+            }
+
+            fn slot_bytes() -> usize {
+                mem::size_of::<K>() + mem::size_of::<V>()
+            }
+
+            unsafe fn key(&self, index: usize) -> &K {
+                self.keys[index].assume_init_ref()
+            }
+
+            unsafe fn value(&self, index: usize) -> &V {
+                self.values[index].assume_init_ref()
+            }
+
+            fn key_ptr(&self, index: usize) -> *const K {
+                assert!(index < self.keys.len());
+                self.keys.as_ptr().wrapping_add(index).cast()
+            }
+
+            fn value_ptr(&mut self, index: usize) -> *mut V {
+                assert!(index < self.values.len());
+                self.values.as_mut_ptr().wrapping_add(index).cast()
+            }
+
+            unsafe fn read(&mut self, index: usize) -> (K, V) {
+                (
+                    self.keys[index].assume_init_read(),
+                    self.values[index].assume_init_read(),
+                )
+            }
+
+            fn write(&mut self, index: usize, key: K, value: V) {
+                self.keys[index] = MaybeUninit::new(key);
+                self.values[index] = MaybeUninit::new(value);
+            }
+
+            fn swap(&mut self, a: usize, b: usize) {
+                self.keys.swap(a, b);
+                self.values.swap(a, b);
+            }
+        }
+    }
+
+    use layout::Columns;
+
+    /// Lookups that scan the slot metadata and cached hashes a group of slots
+    /// at a time: eight with SSE2, sixteen when AVX2 is detected at runtime.
+    /// A slot whose stored PSL is below its distance from the key's home slot
+    /// ends the probe, as in the scalar path; every slot before it in the
+    /// group is occupied, so its cached hash can be compared directly.
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    mod simd {
+        use super::{decode_psl, home_slot, next_slot};
+        use std::arch::x86_64::*;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        /// Past this distance the expected PSLs of a group could overflow the
+        /// `u16` lanes, so the scalar path takes over.
+        const MAX_GROUP_DISTANCE: usize = u16::MAX as usize - 32;
+
+        /// Matching and stopping slots of one group, two mask bits per slot.
+        struct Group {
+            matches: u32,
+            stops: u32,
+        }
+
+        /// Compares the eight slots starting at `meta`/`hashes`, the first of
+        /// which is `distance` slots from home.
+        ///
+        /// # Safety
+        ///
+        /// Eight slots must be readable from both pointers.
+        #[target_feature(enable = "sse2")]
+        unsafe fn group8(meta: *const u16, hashes: *const u32, distance: u16, hash: u32) -> Group {
+            let offsets = _mm_setr_epi16(1, 2, 3, 4, 5, 6, 7, 8);
+            let expected = _mm_add_epi16(_mm_set1_epi16(distance as i16), offsets);
+            let meta = _mm_loadu_si128(meta.cast());
+            // Nonzero exactly where the stored PSL + 1 is below the expected one.
+            let shortfall = _mm_subs_epu16(expected, meta);
+            let stops = _mm_xor_si128(
+                _mm_cmpeq_epi16(shortfall, _mm_setzero_si128()),
+                _mm_set1_epi16(-1),
+            );
+            let hash = _mm_set1_epi32(hash as i32);
+            let low = _mm_cmpeq_epi32(_mm_loadu_si128(hashes.cast()), hash);
+            let high = _mm_cmpeq_epi32(_mm_loadu_si128(hashes.add(4).cast()), hash);
+            let matches = _mm_packs_epi32(low, high);
+            Group {
+                matches: _mm_movemask_epi8(matches) as u32,
+                stops: _mm_movemask_epi8(stops) as u32,
+            }
+        }
+
+        /// Like `group8`, for sixteen slots.
+        ///
+        /// # Safety
+        ///
+        /// AVX2 must be available and sixteen slots readable from both pointers.
+        #[target_feature(enable = "avx2")]
+        unsafe fn group16(meta: *const u16, hashes: *const u32, distance: u16, hash: u32) -> Group {
+            let offsets = _mm256_setr_epi16(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16);
+            let expected = _mm256_add_epi16(_mm256_set1_epi16(distance as i16), offsets);
+            let meta = _mm256_loadu_si256(meta.cast());
+            let shortfall = _mm256_subs_epu16(expected, meta);
+            let stops = _mm256_xor_si256(
+                _mm256_cmpeq_epi16(shortfall, _mm256_setzero_si256()),
+                _mm256_set1_epi16(-1),
+            );
+            let hash = _mm256_set1_epi32(hash as i32);
+            let low = _mm256_cmpeq_epi32(_mm256_loadu_si256(hashes.cast()), hash);
+            let high = _mm256_cmpeq_epi32(_mm256_loadu_si256(hashes.add(8).cast()), hash);
+            // Packing works per 128-bit half; put the four quarters back in order.
+            let matches = _mm256_permute4x64_epi64(_mm256_packs_epi32(low, high), 0b11_01_10_00);
+            Group {
+                matches: _mm256_movemask_epi8(matches) as u32,
+                stops: _mm256_movemask_epi8(stops) as u32,
+            }
+        }
+
+        /// Slots per group on this CPU: 16 with AVX2, otherwise 8. Detected on
+        /// the first call and cached.
+        pub(super) fn group_width() -> usize {
+            static WIDTH: AtomicUsize = AtomicUsize::new(0);
+            match WIDTH.load(Ordering::Relaxed) {
+                0 => {
+                    let width = if is_x86_feature_detected!("avx2") {
+                        16
+                    } else {
+                        8
+                    };
+                    WIDTH.store(width, Ordering::Relaxed);
+                    width
+                }
+                width => width,
+            }
+        }
+
+        /// Returns the slot holding the key with cached hash `hash`, as decided
+        /// by `key_eq` for each slot whose hash matches. Scans `width` slots
+        /// per step: 8, or 16 if `group_width` allows it.
+        pub(super) fn find(
+            meta: &[u16],
+            hashes: &[u32],
+            hash: u32,
+            width: usize,
+            mut key_eq: impl FnMut(usize) -> bool,
+        ) -> Option<usize> {
+            assert!(
+                width == 8 || (width == 16 && group_width() == 16),
+                "unsupported group width"
+            );
+            let mut index = home_slot(hash, meta.len());
+            let mut distance = 0;
+            loop {
+                if index + width > meta.len() || distance > MAX_GROUP_DISTANCE {
+                    // Near the end of the array or far from home: one slot at
+                    // a time, exactly like the scalar probe.
+                    match decode_psl(meta[index], hashes[index], index, meta.len()) {
+                        Some(psl) if psl >= distance => {}
+                        _ => return None,
+                    }
+                    if hashes[index] == hash && key_eq(index) {
+                        return Some(index);
                     }
+                    index = next_slot(index, meta.len());
+                    distance += 1;
+                    continue;
+                }
 
-                    None => {
-                        self.table[hash_id] = Some(key_value);
-                        self.num_entries += 1;
-                        break;
+                let (meta_ptr, hashes_ptr) = (meta[index..].as_ptr(), hashes[index..].as_ptr());
+                // SAFETY: `index + width` is in bounds of both arrays, and
+                // `group16` is only picked when AVX2 was detected, as checked
+                // on entry.
+                let group = unsafe {
+                    if width == 16 {
+                        group16(meta_ptr, hashes_ptr, distance as u16, hash)
+                    } else {
+                        group8(meta_ptr, hashes_ptr, distance as u16, hash)
                     }
-                    _ => panic!("Something went wrong while inserting element!!!"),
+                };
+                let before_stop = match group.stops {
+                    0 => u32::MAX,
+                    stops => (1 << stops.trailing_zeros()) - 1,
+                };
+                let mut matches = group.matches & before_stop & 0x5555_5555;
+                while matches != 0 {
+                    let slot = index + matches.trailing_zeros() as usize / 2;
+                    if key_eq(slot) {
+                        return Some(slot);
+                    }
+                    matches &= matches - 1;
+                }
+                if group.stops != 0 {
+                    return None;
                 }
+                index = (index + width) & (meta.len() - 1);
+                distance += width;
+            }
+        }
+    }
+
+    /// Metadata of an empty slot. An occupied slot stores its PSL plus one.
+    const EMPTY: u16 = 0;
+    /// Metadata of an occupied slot whose PSL is too long to store. Its PSL is
+    /// recomputed from the slot's cached hash instead.
+    const PSL_SATURATED: u16 = u16::MAX;
+    /// Longest PSL stored in the metadata itself. An insert that goes past it
+    /// breaks the probe limit even when no `max_probe_length` is set, so the
+    /// table grows or reseeds before saturated slots pile up.
+    const MAX_STORED_PSL: usize = PSL_SATURATED as usize - 2;
+
+    fn encode_psl(psl: usize) -> u16 {
+        (psl.min(MAX_STORED_PSL + 1) + 1) as u16
+    }
+
+    /// PSL of slot `index` in a table of `capacity` slots, or `None` if the
+    /// slot is empty.
+    fn decode_psl(meta: u16, hash: u32, index: usize, capacity: usize) -> Option<usize> {
+        match meta {
+            EMPTY => None,
+            PSL_SATURATED => Some(index.wrapping_sub(home_slot(hash, capacity)) & (capacity - 1)),
+            meta => Some(usize::from(meta - 1)),
+        }
+    }
+
+    /// A backing array of slots. Occupancy and PSL share one `u16` of metadata
+    /// per slot, and the cached hashes and the keys and values are kept in
+    /// arrays of their own, so a slot costs six bytes plus its key and value
+    /// with no `Option` discriminant or padding. A slot's key and value are
+    /// initialised exactly when its metadata is not `EMPTY`.
+    struct Slots<C: Columns> {
+        meta: Vec<u16>,
+        /// Top 32 bits of each key's mixed hash. Used to re-place entries on
+        /// resize and to skip `K::eq` on fingerprint mismatches.
+        hashes: Vec<u32>,
+        columns: C,
+    }
+
+    impl<C: Columns> Slots<C> {
+        /// An array without any slots, used for `old_table` when no resize is
+        /// running.
+        fn none() -> Self {
+            Self::try_with_capacity(0).expect("empty slots never allocate")
+        }
+
+        fn try_with_capacity(capacity: usize) -> Result<Self, TryReserveError> {
+            let mut meta = Vec::new();
+            meta.try_reserve_exact(capacity)
+                .map_err(TryReserveError::AllocError)?;
+            let mut hashes = Vec::new();
+            hashes
+                .try_reserve_exact(capacity)
+                .map_err(TryReserveError::AllocError)?;
+            let columns = C::try_with_capacity(capacity)?;
+            meta.resize(capacity, EMPTY);
+            hashes.resize(capacity, 0);
+            Ok(Slots {
+                meta,
+                hashes,
+                columns,
+            })
+        }
+
+        fn len(&self) -> usize {
+            self.meta.len()
+        }
+
+        fn is_empty(&self) -> bool {
+            self.meta.is_empty()
+        }
+
+        fn is_occupied(&self, index: usize) -> bool {
+            self.meta[index] != EMPTY
+        }
+
+        /// PSL of the entry at `index`, or `None` if the slot is empty.
+        fn psl(&self, index: usize) -> Option<usize> {
+            decode_psl(self.meta[index], self.hashes[index], index, self.len())
+        }
+
+        fn hash(&self, index: usize) -> u32 {
+            self.hashes[index]
+        }
+
+        fn get(&self, index: usize) -> Option<(&C::Key, &C::Value)> {
+            if self.is_occupied(index) {
+                // SAFETY: occupied slots are initialised.
+                Some(unsafe { (self.columns.key(index), self.columns.value(index)) })
+            } else {
+                None
             }
+        }
+
+        fn value(&self, index: usize) -> Option<&C::Value> {
+            // SAFETY: occupied slots are initialised.
+            self.is_occupied(index)
+                .then(|| unsafe { self.columns.value(index) })
+        }
 
-            let current_load: f64 = self.num_entries as f64 / self.capacity as f64;
-            if current_load >= self.max_load_factor {
-                self.build_resized_table();
+        fn value_mut(&mut self, index: usize) -> Option<&mut C::Value> {
+            if self.is_occupied(index) {
+                // SAFETY: occupied slots are initialised.
+                Some(unsafe { &mut *self.columns.value_ptr(index) })
+            } else {
+                None
             }
         }
 
-        pub fn build_resized_table(&mut self) {
-            let resized_table: Vec<Option<KeyValuePair<K, V>>> = vec![None; self.capacity * 2];
-            let temp_table = self.table.clone();
-            self.table = resized_table;
-            self.capacity *= 2;
+        /// Hands out the entry of slot `index` for as long as the caller
+        /// chooses.
+        ///
+        /// # Safety
+        ///
+        /// The slot must be occupied, and the caller must not hand out the
+        /// same slot's value twice or let the references outlive the slots.
+        unsafe fn get_unchecked_mut<'a>(&mut self, index: usize) -> (&'a C::Key, &'a mut C::Value) {
+            let value = self.columns.value_ptr(index);
+            (&*self.columns.key_ptr(index), &mut *value)
+        }
 
-            for i in 0..temp_table.len() {
-                if !temp_table[i].is_none() {
-                    let new_entry = temp_table[i].as_ref().unwrap().clone();
-                    self.insert(new_entry.key, new_entry.value);
-                }
+        /// Takes the entry at `index` and its cached hash, leaving the slot
+        /// empty. No entries are shifted.
+        fn take(&mut self, index: usize) -> Option<(C::Key, C::Value, u32)> {
+            if !self.is_occupied(index) {
+                return None;
             }
+            self.meta[index] = EMPTY;
+            // SAFETY: the slot was occupied and is now marked empty, so the
+            // entry is read out exactly once.
+            let (key, value) = unsafe { self.columns.read(index) };
+            Some((key, value, self.hashes[index]))
+        }
+
+        /// Moves the entry at `from` back by `distance` slots into the empty
+        /// slot before it, shortening its PSL to match.
+        fn shift_back(&mut self, from: usize, distance: usize) {
+            let to = (from + self.len() - distance) & (self.len() - 1);
+            let psl = self.psl(from).expect("shift_back called on an empty slot");
+            debug_assert!(!self.is_occupied(to) && psl >= distance);
+            self.columns.swap(to, from);
+            self.meta[to] = encode_psl(psl - distance);
+            self.hashes[to] = self.hashes[from];
+            self.meta[from] = EMPTY;
+        }
+
+        /// Index of a slot that starts a run: an empty slot or an entry at its
+        /// home slot. A sweep starting here never splits a run at the
+        /// wraparound.
+        fn run_start(&self) -> usize {
+            (0..self.len())
+                .find(|&index| matches!(self.psl(index), None | Some(0)))
+                .unwrap_or(0)
+        }
+
+        /// Swaps the key of occupied slot `index` for an equal one, returning
+        /// the old key.
+        fn replace_key(&mut self, index: usize, key: C::Key) -> C::Key {
+            assert!(
+                self.is_occupied(index),
+                "replace_key called on an empty slot"
+            );
+            // SAFETY: the slot is occupied, and is written straight back
+            // before anything can panic.
+            let (old_key, value) = unsafe { self.columns.read(index) };
+            self.columns.write(index, key, value);
+            old_key
+        }
+
+        /// Takes every entry out, in slot order.
+        fn take_all(&mut self) -> impl Iterator<Item = (C::Key, C::Value, u32)> + '_ {
+            (0..self.len()).filter_map(move |index| self.take(index))
+        }
+
+        /// Drops every entry, keeping the slots allocated.
+        fn clear(&mut self) {
+            self.take_all().for_each(drop);
         }
 
-        pub fn remove(&mut self, key: K) -> bool {
-            let mut hasher = self.hasher_state.build_hasher();
-            key.hash(&mut hasher);
-            let mut hash_id = hasher.finish() as usize % self.capacity;
+        /// Places an entry at `index` with the given PSL, carrying any entry it
+        /// displaces forward until an empty slot is found. Each swap writes the
+        /// carried entry into the slot and picks up the richer one it evicted.
+        /// Returns the longest PSL written along the way.
+        fn place(
+            &mut self,
+            mut index: usize,
+            mut psl: usize,
+            mut hash: u32,
+            mut key: C::Key,
+            mut value: C::Value,
+        ) -> usize {
+            let mut longest = 0;
             loop {
-                let mut bucket = self.table[hash_id].clone();
-                match bucket {
-                    Some(..) => {
-                        if bucket.unwrap().key == key {
-                            self.table[hash_id] = None;
-                            return true;
+                match self.psl(index) {
+                    Some(resident) => {
+                        if resident < psl {
+                            longest = longest.max(psl);
+                            self.meta[index] = encode_psl(psl);
+                            mem::swap(&mut self.hashes[index], &mut hash);
+                            // SAFETY: the slot is occupied, and is written
+                            // straight back before anything can panic.
+                            let (evicted_key, evicted_value) = unsafe { self.columns.read(index) };
+                            self.columns.write(index, key, value);
+                            key = evicted_key;
+                            value = evicted_value;
+                            psl = resident;
                         }
 
-                        hash_id += 1;
-                        if hash_id >= self.capacity {
-                            hash_id = 0;
-                        }
+                        psl += 1;
+                        index = next_slot(index, self.len());
                     }
+
                     None => {
-                        break;
+                        longest = longest.max(psl);
+                        self.meta[index] = encode_psl(psl);
+                        self.hashes[index] = hash;
+                        self.columns.write(index, key, value);
+                        return longest;
                     }
-                    _ => {
-                        panic!(
-                            "something went wrong while trying to delete {} from table!!!",
-                            key
-                        );
+                }
+            }
+        }
+
+        /// Takes the entry at `index` out along with its cached hash. Entries
+        /// following it are shifted back by one so no tombstones are left
+        /// behind and probe sequences stay short.
+        fn remove(&mut self, mut index: usize) -> (C::Key, C::Value, u32) {
+            let removed = self.take(index).expect("remove called on an empty slot");
+            loop {
+                let next = next_slot(index, self.len());
+                match self.psl(next) {
+                    Some(psl) if psl > 0 => {
+                        // Moving an initialised entry over the empty slot
+                        // before it; `next` is marked empty right after.
+                        self.columns.swap(index, next);
+                        self.meta[index] = encode_psl(psl - 1);
+                        self.hashes[index] = self.hashes[next];
+                        self.meta[next] = EMPTY;
+                        index = next;
                     }
+                    _ => break,
                 }
             }
-            false
+            removed
         }
 
-        pub fn contains(&mut self, key: K) -> bool {
+        /// Bytes allocated for the slots, metadata included.
+        fn allocated_bytes(&self) -> usize {
+            let slot = mem::size_of::<u16>() + mem::size_of::<u32>() + C::slot_bytes();
+            self.len() * slot
+        }
+
+        /// Walks the probe sequence for `key`. Returns `Ok` with the slot
+        /// holding it, or `Err` with the slot and PSL the key would be placed
+        /// at. The search stops early once we reach a bucket that is richer
+        /// than the key would be at that distance, since Robin Hood insertion
+        /// would have displaced it there. Keys are only compared when the
+        /// cached hashes match.
+        fn probe<Q>(&self, hash: u32, key: &Q) -> Result<usize, (usize, usize)>
+        where
+            C::Key: Borrow<Q>,
+            Q: Eq + ?Sized,
+        {
             let mut probing_sequence_len = 0;
-            let mut hasher = self.hasher_state.build_hasher();
-            key.hash(&mut hasher);
-            let mut hash_id = hasher.finish() as usize % self.capacity;
+            let mut hash_id = home_slot(hash, self.len());
             loop {
-                let mut bucket = self.table[hash_id].as_ref();
-                match bucket {
-                    Some(..) => {
-                        if probing_sequence_len > bucket.unwrap().probing_sequence_length {
-                            return false;
+                match self.psl(hash_id) {
+                    Some(psl) => {
+                        if probing_sequence_len > psl {
+                            return Err((hash_id, probing_sequence_len));
                         }
-                        if bucket.unwrap().key == key {
-                            return true;
+                        if self.hash(hash_id) == hash
+                            && self.get(hash_id).unwrap().0.borrow() == key
+                        {
+                            return Ok(hash_id);
                         }
                         probing_sequence_len += 1;
-                        hash_id += 1;
-                        if hash_id >= self.capacity {
-                            hash_id = 0;
-                        }
+                        hash_id = next_slot(hash_id, self.len());
                     }
 
                     None => {
-                        break;
+                        return Err((hash_id, probing_sequence_len));
                     }
+                }
+            }
+        }
+    }
+
+    impl<C: Columns> Slots<C> {
+        /// Returns the slot holding `key`. Gives the same answer as `probe`,
+        /// but with the `simd` feature on x86_64 it scans a group of slots per
+        /// step.
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        fn find<Q>(&self, hash: u32, key: &Q) -> Option<usize>
+        where
+            C::Key: Borrow<Q>,
+            Q: Eq + ?Sized,
+        {
+            self.find_with_width(hash, key, simd::group_width())
+        }
+
+        /// Like `find`, scanning groups of `width` slots whatever this CPU's
+        /// widest supported group is.
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        fn find_with_width<Q>(&self, hash: u32, key: &Q, width: usize) -> Option<usize>
+        where
+            C::Key: Borrow<Q>,
+            Q: Eq + ?Sized,
+        {
+            simd::find(&self.meta, &self.hashes, hash, width, |index| {
+                self.get(index).unwrap().0.borrow() == key
+            })
+        }
+
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        fn find<Q>(&self, hash: u32, key: &Q) -> Option<usize>
+        where
+            C::Key: Borrow<Q>,
+            Q: Eq + ?Sized,
+        {
+            self.probe(hash, key).ok()
+        }
+    }
+
+    impl<C: Columns> Drop for Slots<C> {
+        fn drop(&mut self) {
+            if mem::needs_drop::<C::Key>() || mem::needs_drop::<C::Value>() {
+                self.clear();
+            }
+        }
+    }
 
-                    _ => panic!("something went wrong while searching for {} in table", key),
+    impl<C: Columns> Clone for Slots<C>
+    where
+        C::Key: Clone,
+        C::Value: Clone,
+    {
+        fn clone(&self) -> Self {
+            let mut slots =
+                Self::try_with_capacity(self.len()).unwrap_or_else(|err| panic!("{}", err));
+            slots.hashes.copy_from_slice(&self.hashes);
+            // Metadata is copied slot by slot after each clone, so a panicking
+            // `clone` leaves only initialised entries marked occupied.
+            for index in 0..self.len() {
+                if let Some((key, value)) = self.get(index) {
+                    slots.columns.write(index, key.clone(), value.clone());
+                    slots.meta[index] = self.meta[index];
                 }
             }
-            false
+            slots
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::rh_hash_table::{KeyValuePair, RobinHoodHashTable};
-    use std::hash::{Hash, Hasher};
-    #[test]
-    fn insert_test_for_all_cases() {
-        let mut rht = RobinHoodHashTable::new(0.9, 3);
-        rht.insert(String::from("pineapple"), 1);
-        assert_eq!(rht.contains(String::from("pineapple")), true);
+    /// The error returned when a `RobinHoodHashTableBuilder` is given options
+    /// the table cannot work with.
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum ConfigError {
+        /// The max load factor must be in `(0, 1]`.
+        MaxLoadFactor(f64),
+        /// The min load factor must be at least zero and low enough that a
+        /// freshly grown table does not immediately qualify for shrinking,
+        /// i.e. below `max_load_factor / growth_factor`.
+        MinLoadFactor(f64),
+        /// The growth factor must be a power of two and at least 2.
+        GrowthFactor(usize),
+        /// The max probe sequence length must be at least 1.
+        MaxProbeLength(usize),
+        /// The initial slots could not be allocated.
+        Allocation(TryReserveError),
+    }
 
-        rht.insert(String::from("carrot"), 2);
-        rht.insert(String::from("cucumber"), 3);
+    impl fmt::Display for ConfigError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ConfigError::MaxLoadFactor(load) => {
+                    write!(f, "max load factor {} is not in (0, 1]", load)
+                }
+                ConfigError::MinLoadFactor(load) => write!(
+                    f,
+                    "min load factor {} is negative or too close to the max load factor",
+                    load
+                ),
+                ConfigError::GrowthFactor(factor) => {
+                    write!(f, "growth factor {} is not a power of two >= 2", factor)
+                }
+                ConfigError::MaxProbeLength(length) => {
+                    write!(f, "max probe sequence length {} must be at least 1", length)
+                }
+                ConfigError::Allocation(err) => write!(f, "{}", err),
+            }
+        }
+    }
 
-        assert_eq!(rht.contains(String::from("carrot")), true);
-        assert_eq!(rht.contains(String::from("cucumber")), true);
+    impl Error for ConfigError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            match self {
+                ConfigError::Allocation(err) => Some(err),
+                _ => None,
+            }
+        }
     }
-    #[test]
-    fn contains_test_for_search_key_that_exists() {
-        let mut rht = RobinHoodHashTable::new(0.9, 3);
-        rht.insert("pine tree", 1);
-        assert_eq!(rht.contains("pine tree"), true);
+
+    impl From<TryReserveError> for ConfigError {
+        fn from(err: TryReserveError) -> Self {
+            ConfigError::Allocation(err)
+        }
     }
 
-    #[test]
-    fn contains_test_for_search_key_that_doesnt_exist() {
-        let mut rht = RobinHoodHashTable::<KeyValuePair<&str, i64>>::new(0.9, 3);
-        assert_eq!(rht.contains("pine tree"), false);
+    /// What the table does about an insert that broke its max probe length.
+    /// The insert itself completes; the table reacts at the start of the next
+    /// one.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ProbeLimitAction {
+        Grow,
+        Reseed,
+        /// Neither growing nor reseeding is allowed, e.g. because the table
+        /// was already reseeded at this capacity and is too sparse to grow.
+        Ignore,
     }
 
-    #[test]
-    fn remove_key_from_table() {
-        let mut rht = RobinHoodHashTable::new(0.9, 3);
-        rht.insert("pine tree", 1);
-        assert_eq!(rht.contains("pine tree"), true);
+    /// Reported to the `on_probe_limit` callback by each insert that creates a
+    /// probe sequence longer than the table's max probe length, as soon as it
+    /// does.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct ProbeLimitEvent {
+        pub probe_length: usize,
+        /// Entries, counting the one just inserted.
+        pub len: usize,
+        pub capacity: usize,
+        pub action: ProbeLimitAction,
+    }
+
+    /// The `on_probe_limit` callback, shared by a builder, the tables it
+    /// builds and their clones.
+    #[derive(Clone)]
+    struct ProbeLimitCallback(Arc<dyn Fn(&ProbeLimitEvent) + Send + Sync>);
+
+    impl fmt::Debug for ProbeLimitCallback {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("ProbeLimitCallback")
+        }
+    }
+
+    /// A snapshot of how full the table is and how far entries sit from their
+    /// home slots, as returned by `RobinHoodHashTable::stats`. During an
+    /// incremental resize it covers both backing arrays.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct TableStats {
+        pub len: usize,
+        pub capacity: usize,
+        pub load_factor: f64,
+        pub mean_probe_length: f64,
+        pub probe_length_variance: f64,
+        pub max_probe_length: usize,
+        /// Entry `n` counts the entries that sit `n` slots past their home slot.
+        pub probe_length_histogram: Vec<usize>,
+        /// Entry `n` counts the runs of exactly `n` consecutive occupied slots.
+        /// Entry 0 is always zero.
+        pub cluster_length_histogram: Vec<usize>,
+        /// Number of times the backing array was reallocated since creation.
+        pub resizes: u64,
+        /// Heap bytes held by the slots, including their metadata.
+        pub allocated_bytes: usize,
+    }
+
+    fn bump(histogram: &mut Vec<usize>, index: usize) {
+        if histogram.len() <= index {
+            histogram.resize(index + 1, 0);
+        }
+        histogram[index] += 1;
+    }
 
-        rht.remove("pine tree");
+    /// Adds the length of every run of occupied slots to `histogram`, joining
+    /// the run that wraps from the last slot back to the first.
+    fn add_cluster_lengths(meta: &[u16], histogram: &mut Vec<usize>) {
+        let start = match meta.iter().position(|&meta| meta == EMPTY) {
+            Some(start) => start,
+            None => {
+                if !meta.is_empty() {
+                    bump(histogram, meta.len());
+                }
+                return;
+            }
+        };
+        let mut run = 0;
+        for offset in 1..=meta.len() {
+            if meta[(start + offset) % meta.len()] != EMPTY {
+                run += 1;
+            } else if run > 0 {
+                bump(histogram, run);
+                run = 0;
+            }
+        }
+    }
+
+    /// Configures and builds a `RobinHoodHashTable`, validating every option.
+    #[derive(Debug, Clone)]
+    pub struct RobinHoodHashTableBuilder<S = RandomState> {
+        capacity: usize,
+        max_load_factor: f64,
+        min_load_factor: f64,
+        growth_factor: usize,
+        max_probe_length: Option<usize>,
+        reseeder: Option<fn() -> S>,
+        on_probe_limit: Option<ProbeLimitCallback>,
+        incremental_resize: Option<NonZeroUsize>,
+        hasher_state: S,
+    }
+
+    impl RobinHoodHashTableBuilder {
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl Default for RobinHoodHashTableBuilder {
+        fn default() -> Self {
+            RobinHoodHashTableBuilder {
+                capacity: DEFAULT_CAPACITY,
+                max_load_factor: DEFAULT_MAX_LOAD_FACTOR,
+                min_load_factor: DEFAULT_MIN_LOAD_FACTOR,
+                growth_factor: DEFAULT_GROWTH_FACTOR,
+                max_probe_length: None,
+                reseeder: None,
+                on_probe_limit: None,
+                incremental_resize: None,
+                hasher_state: RandomState::new(),
+            }
+        }
+    }
 
-        assert_eq!(rht.contains("pine tree"), false);
+    impl<S> RobinHoodHashTableBuilder<S> {
+        /// Initial number of slots, rounded up to a power of two.
+        pub fn capacity(mut self, capacity: usize) -> Self {
+            self.capacity = capacity;
+            self
+        }
+
+        /// Load at which an insert grows the table. Must be in `(0, 1]`.
+        pub fn max_load_factor(mut self, max_load: f64) -> Self {
+            self.max_load_factor = max_load;
+            self
+        }
+
+        /// Load below which a removal shrinks the table. Zero never shrinks.
+        pub fn min_load_factor(mut self, min_load: f64) -> Self {
+            self.min_load_factor = min_load;
+            self
+        }
+
+        /// How many times larger the table gets when it grows. Must be a power
+        /// of two so capacities stay powers of two.
+        pub fn growth_factor(mut self, growth_factor: usize) -> Self {
+            self.growth_factor = growth_factor;
+            self
+        }
+
+        /// Longest probe sequence an insert may create before the table is
+        /// forced to grow, or to reseed when `reseed_with` is set. Unbounded by
+        /// default. The insert that breaks the limit still completes and is
+        /// counted and reported straight away; the resize or reseed is
+        /// deferred to the start of the next insert, where `try_insert`
+        /// returns an allocation failure instead of aborting.
+        pub fn max_probe_length(mut self, max_probe_length: usize) -> Self {
+            self.max_probe_length = Some(max_probe_length);
+            self
+        }
+
+        /// When the probe limit is hit, replace the hasher with `reseeder()`
+        /// and rehash every key at the current capacity instead of growing.
+        /// This breaks up chains built from keys chosen against the old seed.
+        /// For `RandomState` pass `RandomState::new`.
+        pub fn reseed_with(mut self, reseeder: fn() -> S) -> Self {
+            self.reseeder = Some(reseeder);
+            self
+        }
+
+        /// Calls `callback` every time an insert breaks the probe limit, from
+        /// within that insert. The callback may capture state, such as a
+        /// metrics handle, and is shared by clones of the table.
+        pub fn on_probe_limit<F>(mut self, callback: F) -> Self
+        where
+            F: Fn(&ProbeLimitEvent) + Send + Sync + 'static,
+        {
+            self.on_probe_limit = Some(ProbeLimitCallback(Arc::new(callback)));
+            self
+        }
+
+        /// Migrate `slots_per_operation` slots per insert or remove when
+        /// resizing, or more if needed to finish before the next resize. See
+        /// `RobinHoodHashTable::set_incremental_resize`.
+        pub fn incremental_resize(mut self, slots_per_operation: NonZeroUsize) -> Self {
+            self.incremental_resize = Some(slots_per_operation);
+            self
+        }
 
-        assert_eq!(rht.remove("pine tree"), false);
+        /// Hash keys with `hasher_state` instead of a fresh `RandomState`. A
+        /// reseeder set earlier is dropped, since it builds the old hasher type.
+        pub fn hasher<S2>(self, hasher_state: S2) -> RobinHoodHashTableBuilder<S2> {
+            RobinHoodHashTableBuilder {
+                capacity: self.capacity,
+                max_load_factor: self.max_load_factor,
+                min_load_factor: self.min_load_factor,
+                growth_factor: self.growth_factor,
+                max_probe_length: self.max_probe_length,
+                reseeder: None,
+                on_probe_limit: self.on_probe_limit,
+                incremental_resize: self.incremental_resize,
+                hasher_state,
+            }
+        }
+
+        fn validate(&self) -> Result<(), ConfigError> {
+            if !(self.max_load_factor > 0.0 && self.max_load_factor <= 1.0) {
+                return Err(ConfigError::MaxLoadFactor(self.max_load_factor));
+            }
+            if self.growth_factor < 2 || !self.growth_factor.is_power_of_two() {
+                return Err(ConfigError::GrowthFactor(self.growth_factor));
+            }
+            let shrink_limit = self.max_load_factor / self.growth_factor as f64;
+            if !(self.min_load_factor >= 0.0 && self.min_load_factor < shrink_limit) {
+                return Err(ConfigError::MinLoadFactor(self.min_load_factor));
+            }
+            if self.max_probe_length == Some(0) {
+                return Err(ConfigError::MaxProbeLength(0));
+            }
+            Ok(())
+        }
+    }
+
+    impl<S: BuildHasher> RobinHoodHashTableBuilder<S> {
+        /// Validates the options and allocates the table.
+        pub fn build<K: Hash + Eq, V>(
+            self,
+        ) -> Result<RobinHoodHashTable<KeyValuePair<K, V>, S>, ConfigError> {
+            self.build_with_layout()
+        }
+
+        /// Like `build`, for a table with another slot layout such as
+        /// `SplitKeyValue`.
+        pub fn build_with_layout<L>(self) -> Result<RobinHoodHashTable<L, S>, ConfigError>
+        where
+            L: SlotLayout,
+            L::Key: Hash + Eq,
+        {
+            self.validate()?;
+            let mut table = RobinHoodHashTable::try_from_parts(
+                self.max_load_factor,
+                self.capacity,
+                self.hasher_state,
+            )?;
+            table.min_load_factor = self.min_load_factor;
+            table.growth_factor = self.growth_factor;
+            table.max_probe_length = self.max_probe_length;
+            table.reseeder = self.reseeder;
+            table.on_probe_limit = self.on_probe_limit;
+            table.migration_batch = self.incremental_resize;
+            Ok(table)
+        }
+    }
+
+    pub const DEFAULT_MAX_LOAD_FACTOR: f64 = 0.9;
+    pub const DEFAULT_MIN_LOAD_FACTOR: f64 = 0.0;
+    pub const DEFAULT_GROWTH_FACTOR: usize = 2;
+    pub const DEFAULT_CAPACITY: usize = 16;
+    /// 2^64 divided by the golden ratio, used to mix hashes before masking.
+    const FIBONACCI_MULTIPLIER: u64 = 0x9E37_79B9_7F4A_7C15;
+
+    /// A Robin Hood hash map. `L` picks the slot layout, `KeyValuePair<K, V>`
+    /// unless stated otherwise; see `SlotLayout`.
+    pub struct RobinHoodHashTable<L: SlotLayout, S = RandomState> {
+        capacity: usize,
+        num_entries: usize,
+        max_load_factor: f64,
+        min_load_factor: f64,
+        growth_factor: usize,
+        /// Longest PSL an insert may create before the next insert grows or
+        /// reseeds the table. `None` leaves probe lengths unbounded.
+        max_probe_length: Option<usize>,
+        /// Reaction to an insert that broke `max_probe_length`, waiting to be
+        /// carried out by the next insert.
+        pending_probe_limit_action: Option<ProbeLimitAction>,
+        probe_limit_hits: u64,
+        resizes: u64,
+        /// Builds a freshly seeded hasher when the probe limit is hit.
+        reseeder: Option<fn() -> S>,
+        /// Set once the hasher has been reseeded at the current capacity.
+        reseeded: bool,
+        on_probe_limit: Option<ProbeLimitCallback>,
+        table: Slots<L::Columns>,
+        /// Slots of the previous backing array that still have to be moved into
+        /// `table` during an incremental resize. Empty when no resize is running.
+        old_table: Slots<L::Columns>,
+        /// Every slot of `old_table` before this index has been migrated.
+        migration_cursor: usize,
+        /// Slots migrated per `insert`/`remove`; `None` resizes in one go.
+        migration_batch: Option<NonZeroUsize>,
+        /// Slots the running resize has to migrate per operation to finish
+        /// before the next resize is due, when that is more than the batch.
+        min_migration_step: usize,
+        hasher_state: S,
+    }
+
+    impl<K, V, S, L: SlotLayout<Key = K, Value = V>> RobinHoodHashTable<L, S> {
+        pub fn len(&self) -> usize {
+            self.num_entries
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.num_entries == 0
+        }
+
+        pub fn capacity(&self) -> usize {
+            self.capacity
+        }
+
+        /// Iterates over the entries in slot order. During an incremental
+        /// resize the entries still waiting in the old slots come last.
+        pub fn iter(&self) -> Iter<'_, K, V, L> {
+            Iter {
+                table: &self.table,
+                old_table: &self.old_table,
+                index: 0,
+                remaining: self.num_entries,
+            }
+        }
+
+        pub fn iter_mut(&mut self) -> IterMut<'_, K, V, L> {
+            IterMut {
+                table: &mut self.table,
+                old_table: &mut self.old_table,
+                index: 0,
+                remaining: self.num_entries,
+            }
+        }
+
+        pub fn keys(&self) -> Keys<'_, K, V, L> {
+            Keys { inner: self.iter() }
+        }
+
+        pub fn values(&self) -> Values<'_, K, V, L> {
+            Values { inner: self.iter() }
+        }
+
+        pub fn values_mut(&mut self) -> ValuesMut<'_, K, V, L> {
+            ValuesMut {
+                inner: self.iter_mut(),
+            }
+        }
+
+        /// Removes every entry, yielding them as owned pairs. The capacity is
+        /// kept; entries not consumed are dropped along with the iterator. If
+        /// the iterator is leaked instead, the entries it has not yielded stay
+        /// in the table.
+        pub fn drain(&mut self) -> Drain<'_, K, V, L> {
+            self.migrate(usize::MAX);
+            self.pending_probe_limit_action = None;
+            // Slots are taken walking backward from the end of a run, so each
+            // one taken is the last of its run and the rest stay reachable.
+            let end = (0..self.capacity)
+                .find(|&index| self.table.psl(index).is_none_or(|psl| psl == 0))
+                .unwrap_or(0);
+            Drain {
+                slots: &mut self.table,
+                num_entries: &mut self.num_entries,
+                index: end,
+            }
+        }
+
+        /// Keeps only the entries for which `keep` returns `true`. The table is
+        /// swept once, shifting kept entries back over the removed ones, rather
+        /// than probing for each removed key.
+        pub fn retain<F>(&mut self, mut keep: F)
+        where
+            F: FnMut(&K, &mut V) -> bool,
+        {
+            // Nothing is handed out mid-sweep, so the holes can be closed
+            // lazily in the one pass.
+            let mut sweep = self.extract_if(|key, value| !keep(key, value));
+            while sweep.sweep(false).is_some() {}
+        }
+
+        /// Removes and yields the entries for which `extract` returns `true`,
+        /// in a single sweep over the slots. Entries the iterator does not get
+        /// to before it is dropped stay in the table. The rest of an extracted
+        /// entry's run is shifted back before the entry is yielded, so even a
+        /// leaked iterator leaves every kept entry reachable.
+        pub fn extract_if<F>(&mut self, extract: F) -> ExtractIf<'_, K, V, F, S, L>
+        where
+            F: FnMut(&K, &mut V) -> bool,
+        {
+            self.migrate(usize::MAX);
+            let start = self.table.run_start();
+            ExtractIf {
+                table: self,
+                start,
+                visited: 0,
+                holes: 0,
+                extract,
+            }
+        }
+
+        /// Makes `insert` and `remove` migrate `slots_per_operation` slots when
+        /// the table resizes, instead of moving every entry at once. If that is
+        /// too few to finish before the next resize is due, each operation
+        /// migrates just enough more to keep up, about three slots when the
+        /// table doubles, so a resize never has to move what the previous one
+        /// left behind. Only a resize forced early, by the probe limit or by
+        /// `reserve` or `shrink_to`, does.
+        /// Lookups consult both backing arrays until the migration finishes.
+        /// Passing `None` switches back to resizing in one go and finishes any
+        /// migration in progress.
+        pub fn set_incremental_resize(&mut self, slots_per_operation: Option<NonZeroUsize>) {
+            self.migration_batch = slots_per_operation;
+            if slots_per_operation.is_none() {
+                self.migrate(usize::MAX);
+            }
+        }
+
+        /// Returns `true` while an incremental resize is still moving entries.
+        pub fn is_resizing(&self) -> bool {
+            !self.old_table.is_empty()
+        }
+
+        /// Moves up to `budget` slots' worth of work from `old_table` into
+        /// `table`, one entry or one empty slot at a time. Entries are taken
+        /// with a backward shift so what is left of `old_table` stays a valid
+        /// Robin Hood table for lookups.
+        fn migrate(&mut self, mut budget: usize) {
+            while budget > 0 && self.migration_cursor < self.old_table.len() {
+                if self.old_table.is_occupied(self.migration_cursor) {
+                    let (key, value, hash) = self.old_table.remove(self.migration_cursor);
+                    self.table
+                        .place(home_slot(hash, self.capacity), 0, hash, key, value);
+                } else {
+                    self.migration_cursor += 1;
+                }
+                budget -= 1;
+            }
+            if self.migration_cursor >= self.old_table.len() {
+                self.old_table = Slots::none();
+                self.migration_cursor = 0;
+            }
+        }
+
+        /// Does one operation's share of an incremental resize, if one is running.
+        fn migrate_step(&mut self) {
+            if self.is_resizing() {
+                let batch = self.migration_batch.map_or(usize::MAX, NonZeroUsize::get);
+                self.migrate(batch.max(self.min_migration_step));
+            }
+        }
+
+        /// Inserts or removes that can follow before the load crosses either
+        /// load factor and the table resizes again, rounded down.
+        fn operations_until_resize(&self) -> usize {
+            let capacity = self.capacity as f64;
+            let grow_at = (self.max_load_factor * capacity) as usize;
+            // The insert that triggered the resize has not been placed yet.
+            let mut operations = grow_at.saturating_sub(self.num_entries + 1);
+            if self.min_load_factor > 0.0 {
+                let shrink_at = (self.min_load_factor * capacity).ceil() as usize;
+                operations = operations.min(self.num_entries.saturating_sub(shrink_at));
+            }
+            operations.max(1)
+        }
+
+        /// Work left in a running incremental resize: each slot still to pass
+        /// and each entry still to move count once, so every slot migrated
+        /// lowers it by one.
+        #[cfg(test)]
+        pub(crate) fn migration_work_left(&self) -> usize {
+            let entries = (self.migration_cursor..self.old_table.len())
+                .filter(|&index| self.old_table.is_occupied(index))
+                .count();
+            self.old_table.len() - self.migration_cursor + entries
+        }
+
+        /// Grows the capacity by the growth factor, either all at once or by
+        /// starting an incremental resize when one is configured.
+        fn grow(&mut self) {
+            self.resize_to(self.grown_capacity(), true);
+        }
+
+        /// Grows the capacity by the growth factor (doubling by default) and
+        /// moves every entry into the new slots.
+        pub fn build_resized_table(&mut self) {
+            self.resize_to(self.grown_capacity(), false);
+        }
+
+        fn grown_capacity(&self) -> usize {
+            match self.try_grown_capacity() {
+                Ok(capacity) => capacity,
+                Err(err) => panic!("{}", err),
+            }
+        }
+
+        fn try_grown_capacity(&self) -> Result<usize, TryReserveError> {
+            self.capacity
+                .checked_mul(self.growth_factor)
+                .ok_or(TryReserveError::CapacityOverflow)
+        }
+
+        /// Number of inserts that created a probe sequence longer than the max
+        /// probe length since the table was created.
+        pub fn probe_limit_hits(&self) -> u64 {
+            self.probe_limit_hits
+        }
+
+        /// Walks every slot to summarise the load and the probe sequence
+        /// lengths, for picking load factors from real key sets.
+        pub fn stats(&self) -> TableStats {
+            let mut probe_length_histogram = Vec::new();
+            let mut cluster_length_histogram = vec![0];
+            let mut sum = 0.0;
+            let mut sum_of_squares = 0.0;
+            for slots in [&self.table, &self.old_table].iter() {
+                for psl in (0..slots.len()).filter_map(|index| slots.psl(index)) {
+                    bump(&mut probe_length_histogram, psl);
+                    sum += psl as f64;
+                    sum_of_squares += (psl * psl) as f64;
+                }
+                add_cluster_lengths(&slots.meta, &mut cluster_length_histogram);
+            }
+
+            let (mean, variance) = if self.num_entries == 0 {
+                (0.0, 0.0)
+            } else {
+                let n = self.num_entries as f64;
+                let mean = sum / n;
+                (mean, (sum_of_squares / n - mean * mean).max(0.0))
+            };
+            TableStats {
+                len: self.num_entries,
+                capacity: self.capacity,
+                load_factor: self.num_entries as f64 / self.capacity as f64,
+                mean_probe_length: mean,
+                probe_length_variance: variance,
+                max_probe_length: probe_length_histogram.len().saturating_sub(1),
+                probe_length_histogram,
+                cluster_length_histogram,
+                resizes: self.resizes,
+                allocated_bytes: self.table.allocated_bytes() + self.old_table.allocated_bytes(),
+            }
+        }
+
+        fn resize_to(&mut self, new_capacity: usize, incremental: bool) {
+            if let Err(err) = self.try_resize_to(new_capacity, incremental) {
+                panic!("{}", err);
+            }
+        }
+
+        /// Moves every entry into a backing array of `new_capacity` slots. Keys
+        /// are already unique and carry their cached hash, so each one is placed
+        /// from its home slot without rehashing or probing for an existing copy.
+        /// With `incremental` set and a migration batch configured, the entries
+        /// are moved over the following operations instead. The new slots are
+        /// allocated before anything is moved, so on error the table is intact.
+        fn try_resize_to(
+            &mut self,
+            new_capacity: usize,
+            incremental: bool,
+        ) -> Result<(), TryReserveError> {
+            let new_table = Slots::try_with_capacity(new_capacity)?;
+            self.migrate(usize::MAX);
+            let mut temp_table = mem::replace(&mut self.table, new_table);
+            self.capacity = new_capacity;
+            self.reseeded = false;
+            self.resizes += 1;
+            self.migration_cursor = 0;
+            if incremental && self.migration_batch.is_some() {
+                // Every entry is moved and every slot passed once; spread
+                // that over the operations left before the next resize.
+                let work = temp_table.len() + self.num_entries;
+                self.min_migration_step = work.div_ceil(self.operations_until_resize());
+                self.old_table = temp_table;
+                return Ok(());
+            }
+
+            for (key, value, hash) in temp_table.take_all() {
+                self.table
+                    .place(home_slot(hash, self.capacity), 0, hash, key, value);
+            }
+            Ok(())
+        }
+
+        /// Smallest power-of-two capacity that holds `entries` without reaching
+        /// the max load factor.
+        fn capacity_for(&self, entries: usize) -> usize {
+            match self.try_capacity_for(entries) {
+                Ok(capacity) => capacity,
+                Err(err) => panic!("{}", err),
+            }
+        }
+
+        fn try_capacity_for(&self, entries: usize) -> Result<usize, TryReserveError> {
+            let mut capacity: usize = 1;
+            while entries as f64 / capacity as f64 >= self.max_load_factor {
+                capacity = capacity
+                    .checked_mul(2)
+                    .ok_or(TryReserveError::CapacityOverflow)?;
+            }
+            Ok(capacity)
+        }
+
+        /// Makes room for at least `additional` more entries without growing.
+        pub fn reserve(&mut self, additional: usize) {
+            if let Err(err) = self.try_reserve(additional) {
+                panic!("{}", err);
+            }
+        }
+
+        /// Like `reserve`, but returns an error instead of panicking or aborting
+        /// when the capacity overflows or the allocation fails. The table is
+        /// left unchanged on error.
+        pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+            let entries = self
+                .num_entries
+                .checked_add(additional)
+                .ok_or(TryReserveError::CapacityOverflow)?;
+            let capacity = self.try_capacity_for(entries)?;
+            if capacity > self.capacity {
+                self.try_resize_to(capacity, false)?;
+            }
+            Ok(())
+        }
+
+        /// Shrinks the capacity as far as the max load factor allows.
+        pub fn shrink_to_fit(&mut self) {
+            self.shrink_to(0);
+        }
+
+        /// Shrinks the capacity to the smallest power of two that is at least
+        /// `min_capacity` and still holds every entry below the max load factor.
+        /// Does nothing if the table is already that small, including when
+        /// `min_capacity` is above the current capacity.
+        pub fn shrink_to(&mut self, min_capacity: usize) {
+            let min_capacity = match min_capacity.checked_next_power_of_two() {
+                Some(min_capacity) if min_capacity < self.capacity => min_capacity,
+                _ => return,
+            };
+            let capacity = self.capacity_for(self.num_entries).max(min_capacity);
+            if capacity < self.capacity {
+                self.resize_to(capacity, false);
+            }
+        }
+
+        /// Sets the load factor below which a removal shrinks the table. Zero,
+        /// the default, never shrinks automatically. The same bounds as
+        /// `RobinHoodHashTableBuilder::min_load_factor` apply.
+        pub fn set_min_load_factor(&mut self, min_load: f64) -> Result<(), ConfigError> {
+            let shrink_limit = self.max_load_factor / self.growth_factor as f64;
+            if !(min_load >= 0.0 && min_load < shrink_limit) {
+                return Err(ConfigError::MinLoadFactor(min_load));
+            }
+            self.min_load_factor = min_load;
+            Ok(())
+        }
+
+        /// Halves the capacity as often as needed once removals push the load
+        /// below the min load factor.
+        fn shrink_if_sparse(&mut self) {
+            let current_load = self.num_entries as f64 / self.capacity as f64;
+            if current_load < self.min_load_factor {
+                let capacity = self.capacity_for(self.num_entries);
+                if capacity < self.capacity {
+                    self.resize_to(capacity, true);
+                }
+            }
+        }
+
+        /// Removes every entry, keeping the allocated capacity.
+        pub fn clear(&mut self) {
+            self.pending_probe_limit_action = None;
+            self.table.clear();
+            self.old_table = Slots::none();
+            self.migration_cursor = 0;
+            self.num_entries = 0;
+        }
+
+        fn place(
+            &mut self,
+            hash_id: usize,
+            probing_sequence_length: usize,
+            hash: u32,
+            key: K,
+            value: V,
+        ) {
+            debug_assert!(
+                self.num_entries < self.capacity,
+                "placing into a full table would never find an empty slot"
+            );
+            let longest = self
+                .table
+                .place(hash_id, probing_sequence_length, hash, key, value);
+            self.num_entries += 1;
+            let limit = self
+                .max_probe_length
+                .map_or(MAX_STORED_PSL, |limit| limit.min(MAX_STORED_PSL));
+            if longest > limit {
+                self.probe_limit_broken(longest);
+            }
+        }
+
+        /// Counts and reports an insert that created a probe sequence of
+        /// `probe_length`, past the limit, and queues the table's reaction for
+        /// the next insert. With a reseeder the hasher is reseeded once per
+        /// capacity; otherwise, or if that did not help, the table grows.
+        /// Growth is skipped once the table would fall below a quarter of the
+        /// max load factor, so a key set whose hashes collide outright cannot
+        /// make it grow without bound.
+        fn probe_limit_broken(&mut self, probe_length: usize) {
+            let action = match self.try_grown_capacity() {
+                _ if self.reseeder.is_some() && !self.reseeded => ProbeLimitAction::Reseed,
+                Ok(grown_capacity)
+                    if (self.num_entries as f64 / grown_capacity as f64)
+                        < self.max_load_factor / 4.0 =>
+                {
+                    ProbeLimitAction::Ignore
+                }
+                // Growing past the largest capacity fails when it is tried.
+                _ => ProbeLimitAction::Grow,
+            };
+            self.probe_limit_hits += 1;
+            if action != ProbeLimitAction::Ignore {
+                self.pending_probe_limit_action = Some(action);
+            }
+            if let Some(callback) = &self.on_probe_limit {
+                (callback.0)(&ProbeLimitEvent {
+                    probe_length,
+                    len: self.num_entries,
+                    capacity: self.capacity,
+                    action,
+                });
+            }
+        }
+
+        fn remove_at(&mut self, hash_id: usize) -> (K, V) {
+            self.num_entries -= 1;
+            let (key, value, _) = self.table.remove(hash_id);
+            (key, value)
+        }
+    }
+
+    impl<K: Clone, V: Clone, S: Clone, L> Clone for RobinHoodHashTable<L, S>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        fn clone(&self) -> Self {
+            RobinHoodHashTable {
+                capacity: self.capacity,
+                num_entries: self.num_entries,
+                max_load_factor: self.max_load_factor,
+                min_load_factor: self.min_load_factor,
+                growth_factor: self.growth_factor,
+                max_probe_length: self.max_probe_length,
+                pending_probe_limit_action: self.pending_probe_limit_action,
+                probe_limit_hits: self.probe_limit_hits,
+                resizes: self.resizes,
+                reseeder: self.reseeder,
+                reseeded: self.reseeded,
+                on_probe_limit: self.on_probe_limit.clone(),
+                table: self.table.clone(),
+                old_table: self.old_table.clone(),
+                migration_cursor: self.migration_cursor,
+                migration_batch: self.migration_batch,
+                min_migration_step: self.min_migration_step,
+                hasher_state: self.hasher_state.clone(),
+            }
+        }
+    }
+
+    impl<K: Hash + Eq, V> RobinHoodHashTable<KeyValuePair<K, V>> {
+        /// Creates an empty table with the default capacity and max load factor.
+        /// Currently we create a hasher using the default SipHash implementation.
+        /// Use `RobinHoodHashTableBuilder` to configure anything else.
+        pub fn new() -> Self {
+            Self::with_capacity(DEFAULT_CAPACITY)
+        }
+
+        /// Creates an empty table with at least `capacity` slots. The capacity
+        /// is rounded up to the next power of two.
+        pub fn with_capacity(capacity: usize) -> Self {
+            Self::with_capacity_and_hasher(capacity, RandomState::new())
+        }
+
+        /// Creates a table with room for `capacity` slots, returning an error
+        /// instead of aborting if they cannot be allocated.
+        pub fn try_with_capacity(capacity: usize) -> Result<Self, TryReserveError> {
+            Self::try_with_capacity_and_hasher(capacity, RandomState::new())
+        }
+    }
+
+    impl<K, V, S, L> Default for RobinHoodHashTable<L, S>
+    where
+        K: Hash + Eq,
+        S: BuildHasher + Default,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        fn default() -> Self {
+            Self::from_parts(DEFAULT_MAX_LOAD_FACTOR, DEFAULT_CAPACITY, S::default())
+        }
+    }
+
+    impl<K: Hash + Eq, V, S: BuildHasher> RobinHoodHashTable<KeyValuePair<K, V>, S> {
+        /// Creates a table that hashes keys with `hasher_state`, using the default
+        /// capacity and max load factor.
+        pub fn with_hasher(hasher_state: S) -> Self {
+            Self::with_capacity_and_hasher(DEFAULT_CAPACITY, hasher_state)
+        }
+
+        pub fn with_capacity_and_hasher(capacity: usize, hasher_state: S) -> Self {
+            Self::from_parts(DEFAULT_MAX_LOAD_FACTOR, capacity, hasher_state)
+        }
+
+        /// Like `with_capacity_and_hasher`, but returns an error if the slots
+        /// cannot be allocated.
+        pub fn try_with_capacity_and_hasher(
+            capacity: usize,
+            hasher_state: S,
+        ) -> Result<Self, TryReserveError> {
+            Self::try_from_parts(DEFAULT_MAX_LOAD_FACTOR, capacity, hasher_state)
+        }
+    }
+
+    impl<K, V, S, L> RobinHoodHashTable<L, S>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        fn from_parts(max_load: f64, capacity: usize, hasher_state: S) -> Self {
+            match Self::try_from_parts(max_load, capacity, hasher_state) {
+                Ok(table) => table,
+                Err(err) => panic!("{}", err),
+            }
+        }
+
+        fn try_from_parts(
+            max_load: f64,
+            capacity: usize,
+            hasher_state: S,
+        ) -> Result<Self, TryReserveError> {
+            let capacity = capacity
+                .checked_next_power_of_two()
+                .ok_or(TryReserveError::CapacityOverflow)?;
+            Ok(Self {
+                capacity,
+                num_entries: 0,
+                max_load_factor: max_load,
+                min_load_factor: DEFAULT_MIN_LOAD_FACTOR,
+                growth_factor: DEFAULT_GROWTH_FACTOR,
+                max_probe_length: None,
+                pending_probe_limit_action: None,
+                probe_limit_hits: 0,
+                resizes: 0,
+                reseeder: None,
+                reseeded: false,
+                on_probe_limit: None,
+                table: Slots::try_with_capacity(capacity)?,
+                old_table: Slots::none(),
+                migration_cursor: 0,
+                migration_batch: None,
+                min_migration_step: 0,
+                hasher_state,
+            })
+        }
+
+        /// Returns a reference to the table's `BuildHasher`.
+        pub fn hasher(&self) -> &S {
+            &self.hasher_state
+        }
+
+        /// Like `insert`, but any growth the insertion needs, including a
+        /// resize or reseed still pending from a broken probe limit, is
+        /// allocated fallibly first. On error nothing is inserted.
+        pub fn try_insert(&mut self, key: K, value: V) -> Result<Option<V>, TryReserveError> {
+            self.try_enforce_probe_limit()?;
+            let capacity = self.try_capacity_for(self.num_entries + 1)?;
+            if capacity > self.capacity {
+                // Grow by the growth factor, as `insert` would.
+                let capacity = capacity.max(self.try_grown_capacity()?);
+                self.try_resize_to(capacity, true)?;
+            }
+            Ok(self.insert(key, value))
+        }
+
+        /// Inserts `value` under `key`. If the key is already present its value
+        /// is replaced and the previous value is returned, otherwise `None`.
+        pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+            match self.entry(key) {
+                Entry::Occupied(mut entry) => Some(entry.insert(value)),
+                Entry::Vacant(entry) => {
+                    entry.insert(value);
+                    None
+                }
+            }
+        }
+
+        /// Gets the entry for `key` for in-place manipulation. The probe is done
+        /// once; a vacant entry remembers where the key would be placed. During
+        /// an incremental resize a key still in the old slots is moved over first.
+        pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S, L> {
+            self.enforce_probe_limit();
+            self.migrate_step();
+            let hash = self.hash_key(&key);
+            if self.is_resizing() {
+                if let Ok(hash_id) = self.old_table.probe(hash, &key) {
+                    let (key, value, _) = self.old_table.remove(hash_id);
+                    self.table
+                        .place(home_slot(hash, self.capacity), 0, hash, key, value);
+                }
+            }
+            match self.probe(hash, &key) {
+                Ok(index) => Entry::Occupied(OccupiedEntry {
+                    table: self,
+                    key,
+                    index,
+                }),
+                Err((index, probing_sequence_length)) => Entry::Vacant(VacantEntry {
+                    table: self,
+                    key,
+                    hash,
+                    index,
+                    probing_sequence_length,
+                }),
+            }
+        }
+
+        /// Removes `key` from the table, returning its value if it was present.
+        pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            self.remove_entry(key).map(|(_, value)| value)
+        }
+
+        /// Removes `key` from the table, returning the stored key and value.
+        /// Entries following the removed slot are shifted back by one so no
+        /// tombstones are left behind and probe sequences stay short.
+        pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            self.migrate_step();
+            let hash = self.hash_key(key);
+            let removed = match self.probe(hash, key) {
+                Ok(hash_id) => self.remove_at(hash_id),
+                Err(..) if self.is_resizing() => {
+                    let hash_id = self.old_table.probe(hash, key).ok()?;
+                    self.num_entries -= 1;
+                    let (key, value, _) = self.old_table.remove(hash_id);
+                    (key, value)
+                }
+                Err(..) => return None,
+            };
+            self.shrink_if_sparse();
+            Some(removed)
+        }
+
+        pub fn contains<Q>(&self, key: &Q) -> bool
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            self.find(key).is_some()
+        }
+
+        /// Returns a reference to the value stored for `key`, if any.
+        pub fn get<Q>(&self, key: &Q) -> Option<&V>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            let (slots, hash_id) = self.find(key)?;
+            slots.value(hash_id)
+        }
+
+        /// Returns a mutable reference to the value stored for `key`, if any.
+        pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            let hash = self.hash_key(key);
+            let (slots, hash_id) = match self.table.find(hash, key) {
+                Some(hash_id) => (&mut self.table, hash_id),
+                None if self.is_resizing() => {
+                    let hash_id = self.old_table.find(hash, key)?;
+                    (&mut self.old_table, hash_id)
+                }
+                None => return None,
+            };
+            slots.value_mut(hash_id)
+        }
+
+        /// Returns the stored key together with its value, if `key` is present.
+        pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            let (slots, hash_id) = self.find(key)?;
+            slots.get(hash_id)
+        }
+
+        fn enforce_probe_limit(&mut self) {
+            if let Err(err) = self.try_enforce_probe_limit() {
+                panic!("{}", err);
+            }
+        }
+
+        /// Carries out the reaction queued by `probe_limit_broken`. On error
+        /// the table is unchanged and the reaction stays pending for the next
+        /// insert.
+        fn try_enforce_probe_limit(&mut self) -> Result<(), TryReserveError> {
+            match (self.pending_probe_limit_action, self.reseeder) {
+                (Some(ProbeLimitAction::Reseed), Some(reseeder)) => self.try_reseed(reseeder())?,
+                (Some(ProbeLimitAction::Grow), _) => {
+                    self.try_resize_to(self.try_grown_capacity()?, true)?
+                }
+                _ => {}
+            }
+            self.pending_probe_limit_action = None;
+            Ok(())
+        }
+
+        /// Replaces the hasher and re-places every entry under its new hash,
+        /// keeping the current capacity. The entries are moved out into a
+        /// buffer allocated before anything changes, so on error the table and
+        /// its hasher are intact.
+        fn try_reseed(&mut self, hasher_state: S) -> Result<(), TryReserveError> {
+            let mut entries = Vec::new();
+            entries
+                .try_reserve_exact(self.num_entries)
+                .map_err(TryReserveError::AllocError)?;
+            self.migrate(usize::MAX);
+            self.hasher_state = hasher_state;
+            self.reseeded = true;
+            entries.extend(self.table.take_all().map(|(key, value, _)| (key, value)));
+            for (key, value) in entries {
+                let hash = self.hash_key(&key);
+                self.table
+                    .place(home_slot(hash, self.capacity), 0, hash, key, value);
+            }
+            Ok(())
+        }
+
+        /// Hashes a key and mixes it with a fibonacci multiply, keeping the top
+        /// 32 bits. Those are the best mixed bits, so weak hashers that only
+        /// vary the low bits still spread across the table.
+        fn hash_key<Q: Hash + ?Sized>(&self, key: &Q) -> u32 {
+            let mixed = self
+                .hasher_state
+                .hash_one(key)
+                .wrapping_mul(FIBONACCI_MULTIPLIER);
+            (mixed >> 32) as u32
+        }
+
+        /// Finds the slot holding `key`, falling back to the old slots while
+        /// an incremental resize is in progress.
+        fn find<Q>(&self, key: &Q) -> Option<(&Slots<L::Columns>, usize)>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            let hash = self.hash_key(key);
+            match self.table.find(hash, key) {
+                Some(hash_id) => Some((&self.table, hash_id)),
+                None if self.is_resizing() => {
+                    Some((&self.old_table, self.old_table.find(hash, key)?))
+                }
+                None => None,
+            }
+        }
+
+        /// Like `get`, scanning groups of `width` slots, so tests can check
+        /// each group width on any CPU that supports it.
+        #[cfg(all(test, feature = "simd", target_arch = "x86_64"))]
+        pub(crate) fn get_with_group_width<Q>(&self, key: &Q, width: usize) -> Option<&V>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            let hash = self.hash_key(key);
+            match self.table.find_with_width(hash, key, width) {
+                Some(hash_id) => self.table.value(hash_id),
+                None if self.is_resizing() => {
+                    let hash_id = self.old_table.find_with_width(hash, key, width)?;
+                    self.old_table.value(hash_id)
+                }
+                None => None,
+            }
+        }
+
+        fn probe<Q>(&self, hash: u32, key: &Q) -> Result<usize, (usize, usize)>
+        where
+            K: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            self.table.probe(hash, key)
+        }
+
+        /// Checks the Robin Hood invariants and panics if one is broken: each
+        /// slot's PSL equals its distance from its home slot, a PSL is at most
+        /// one more than the PSL of the slot before it (so a run starts at PSL
+        /// zero), cached hashes match their keys and the entry count is right.
+        /// During an incremental resize both backing arrays are checked.
+        /// Does nothing when debug assertions are disabled.
+        pub fn debug_assert_invariants(&self) {
+            if !cfg!(debug_assertions) {
+                return;
+            }
+            let mut occupied = self.assert_slot_invariants(&self.table);
+            if self.is_resizing() {
+                assert!(
+                    self.old_table.meta[..self.migration_cursor]
+                        .iter()
+                        .all(|&meta| meta == EMPTY),
+                    "old slots before the migration cursor still hold entries"
+                );
+                occupied += self.assert_slot_invariants(&self.old_table);
+            }
+            assert_eq!(occupied, self.num_entries, "entry count is out of sync");
+        }
+
+        fn assert_slot_invariants(&self, slots: &Slots<L::Columns>) -> usize {
+            let mask = slots.len() - 1;
+            let mut occupied = 0;
+            for hash_id in 0..slots.len() {
+                let (key, psl) = match (slots.get(hash_id), slots.psl(hash_id)) {
+                    (Some((key, _)), Some(psl)) => (key, psl),
+                    _ => continue,
+                };
+                occupied += 1;
+                let hash = slots.hash(hash_id);
+                assert_eq!(
+                    hash,
+                    self.hash_key(key),
+                    "slot {} holds a stale cached hash",
+                    hash_id
+                );
+                let distance = hash_id.wrapping_sub(home_slot(hash, slots.len())) & mask;
+                assert_eq!(
+                    psl, distance,
+                    "slot {} has a PSL of {} but sits {} slots from home",
+                    hash_id, psl, distance
+                );
+                if psl > 0 {
+                    let previous_psl = slots.psl(hash_id.wrapping_sub(1) & mask);
+                    assert!(
+                        previous_psl.is_some_and(|previous| psl <= previous + 1),
+                        "slot {} has a PSL of {} after {:?}",
+                        hash_id,
+                        psl,
+                        previous_psl
+                    );
+                }
+            }
+            occupied
+        }
+    }
+
+    /// A view into a single slot of a `RobinHoodHashTable`, obtained from
+    /// `RobinHoodHashTable::entry`.
+    pub enum Entry<'a, K, V, S = RandomState, L = KeyValuePair<K, V>>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        Occupied(OccupiedEntry<'a, K, V, S, L>),
+        Vacant(VacantEntry<'a, K, V, S, L>),
+    }
+
+    pub struct OccupiedEntry<'a, K, V, S = RandomState, L = KeyValuePair<K, V>>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        table: &'a mut RobinHoodHashTable<L, S>,
+        /// The key the entry was looked up with, for `replace_key`.
+        key: K,
+        index: usize,
+    }
+
+    /// A key that is not in the table, along with the slot and PSL where it
+    /// would be inserted.
+    pub struct VacantEntry<'a, K, V, S = RandomState, L = KeyValuePair<K, V>>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        table: &'a mut RobinHoodHashTable<L, S>,
+        key: K,
+        hash: u32,
+        index: usize,
+        probing_sequence_length: usize,
+    }
+
+    impl<'a, K, V, S, L> Entry<'a, K, V, S, L>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        pub fn key(&self) -> &K {
+            match self {
+                Entry::Occupied(entry) => entry.key(),
+                Entry::Vacant(entry) => entry.key(),
+            }
+        }
+
+        pub fn or_insert(self, default: V) -> &'a mut V {
+            match self {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(default),
+            }
+        }
+
+        pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+            match self {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(default()),
+            }
+        }
+
+        pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+            match self {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let value = default(entry.key());
+                    entry.insert(value)
+                }
+            }
+        }
+
+        /// Runs `f` on the value if the entry is occupied.
+        pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+            match self {
+                Entry::Occupied(mut entry) => {
+                    f(entry.get_mut());
+                    Entry::Occupied(entry)
+                }
+                Entry::Vacant(entry) => Entry::Vacant(entry),
+            }
+        }
+    }
+
+    impl<'a, K, V, S, L> Entry<'a, K, V, S, L>
+    where
+        K: Hash + Eq,
+        V: Default,
+        S: BuildHasher,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        pub fn or_default(self) -> &'a mut V {
+            self.or_insert_with(V::default)
+        }
+    }
+
+    impl<'a, K, V, S, L> OccupiedEntry<'a, K, V, S, L>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        pub fn key(&self) -> &K {
+            self.table.table.get(self.index).unwrap().0
+        }
+
+        pub fn get(&self) -> &V {
+            self.table.table.get(self.index).unwrap().1
+        }
+
+        pub fn get_mut(&mut self) -> &mut V {
+            self.table.table.value_mut(self.index).unwrap()
+        }
+
+        /// Converts the entry into a mutable reference tied to the table's lifetime.
+        pub fn into_mut(self) -> &'a mut V {
+            self.table.table.value_mut(self.index).unwrap()
+        }
+
+        /// Replaces the value, returning the old one.
+        pub fn insert(&mut self, value: V) -> V {
+            mem::replace(self.get_mut(), value)
+        }
+
+        /// Removes the entry from the table, shifting its cluster back.
+        pub fn remove(self) -> V {
+            self.remove_entry().1
+        }
+
+        /// Swaps the stored key for the one the entry was looked up with,
+        /// returning the stored one.
+        fn replace_key(self) -> K {
+            self.table.table.replace_key(self.index, self.key)
+        }
+
+        pub fn remove_entry(self) -> (K, V) {
+            let removed = self.table.remove_at(self.index);
+            self.table.shrink_if_sparse();
+            removed
+        }
+    }
+
+    impl<'a, K, V, S, L> VacantEntry<'a, K, V, S, L>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        pub fn key(&self) -> &K {
+            &self.key
+        }
+
+        pub fn into_key(self) -> K {
+            self.key
+        }
+
+        /// Inserts `value` at the remembered slot and returns a reference to it.
+        /// If the insertion would reach the max load factor the table is grown
+        /// first and the slot is probed again.
+        pub fn insert(self, value: V) -> &'a mut V {
+            let table = self.table;
+            let (mut index, mut probing_sequence_length) =
+                (self.index, self.probing_sequence_length);
+            let next_load = (table.num_entries + 1) as f64 / table.capacity as f64;
+            if next_load >= table.max_load_factor {
+                table.grow();
+                match table.probe(self.hash, &self.key) {
+                    Err(slot) => (index, probing_sequence_length) = slot,
+                    Ok(..) => unreachable!("vacant key was found after resizing"),
+                }
+            }
+            table.place(index, probing_sequence_length, self.hash, self.key, value);
+            table.table.value_mut(index).unwrap()
+        }
+    }
+
+    pub struct Iter<'a, K, V, L = KeyValuePair<K, V>>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        table: &'a Slots<L::Columns>,
+        old_table: &'a Slots<L::Columns>,
+        /// Index into `table` followed by `old_table`.
+        index: usize,
+        remaining: usize,
+    }
+
+    impl<'a, K: 'a, V: 'a, L: SlotLayout<Key = K, Value = V>> Iterator for Iter<'a, K, V, L> {
+        type Item = (&'a K, &'a V);
+
+        fn next(&mut self) -> Option<Self::Item> {
+            while self.remaining > 0 {
+                let index = self.index;
+                self.index += 1;
+                let entry = match index.checked_sub(self.table.len()) {
+                    None => self.table.get(index),
+                    Some(old_index) => self.old_table.get(old_index),
+                };
+                if entry.is_some() {
+                    self.remaining -= 1;
+                    return entry;
+                }
+            }
+            None
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.remaining, Some(self.remaining))
+        }
+    }
+
+    impl<'a, K: 'a, V: 'a, L: SlotLayout<Key = K, Value = V>> ExactSizeIterator for Iter<'a, K, V, L> {}
+
+    impl<'a, K: 'a, V: 'a, L: SlotLayout<Key = K, Value = V>> Clone for Iter<'a, K, V, L> {
+        fn clone(&self) -> Self {
+            Iter {
+                table: self.table,
+                old_table: self.old_table,
+                index: self.index,
+                remaining: self.remaining,
+            }
+        }
+    }
+
+    pub struct IterMut<'a, K, V, L = KeyValuePair<K, V>>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        table: &'a mut Slots<L::Columns>,
+        old_table: &'a mut Slots<L::Columns>,
+        /// Index into `table` followed by `old_table`.
+        index: usize,
+        remaining: usize,
+    }
+
+    impl<'a, K: 'a, V: 'a, L: SlotLayout<Key = K, Value = V>> Iterator for IterMut<'a, K, V, L> {
+        type Item = (&'a K, &'a mut V);
+
+        fn next(&mut self) -> Option<Self::Item> {
+            while self.remaining > 0 {
+                let index = self.index;
+                self.index += 1;
+                let (slots, index) = match index.checked_sub(self.table.len()) {
+                    None => (&mut *self.table, index),
+                    Some(old_index) => (&mut *self.old_table, old_index),
+                };
+                if slots.is_occupied(index) {
+                    self.remaining -= 1;
+                    // SAFETY: every slot is visited once, and both arrays stay
+                    // borrowed for `'a`.
+                    return Some(unsafe { slots.get_unchecked_mut(index) });
+                }
+            }
+            None
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.remaining, Some(self.remaining))
+        }
+    }
+
+    impl<'a, K: 'a, V: 'a, L: SlotLayout<Key = K, Value = V>> ExactSizeIterator
+        for IterMut<'a, K, V, L>
+    {
+    }
+
+    pub struct Keys<'a, K, V, L = KeyValuePair<K, V>>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        inner: Iter<'a, K, V, L>,
+    }
+
+    impl<'a, K: 'a, V: 'a, L: SlotLayout<Key = K, Value = V>> Iterator for Keys<'a, K, V, L> {
+        type Item = &'a K;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.inner.next().map(|(key, _)| key)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.inner.size_hint()
+        }
+    }
+
+    impl<'a, K: 'a, V: 'a, L: SlotLayout<Key = K, Value = V>> ExactSizeIterator for Keys<'a, K, V, L> {}
+
+    pub struct Values<'a, K, V, L = KeyValuePair<K, V>>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        inner: Iter<'a, K, V, L>,
+    }
+
+    impl<'a, K: 'a, V: 'a, L: SlotLayout<Key = K, Value = V>> Iterator for Values<'a, K, V, L> {
+        type Item = &'a V;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.inner.next().map(|(_, value)| value)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.inner.size_hint()
+        }
+    }
+
+    impl<'a, K: 'a, V: 'a, L: SlotLayout<Key = K, Value = V>> ExactSizeIterator
+        for Values<'a, K, V, L>
+    {
+    }
+
+    pub struct ValuesMut<'a, K, V, L = KeyValuePair<K, V>>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        inner: IterMut<'a, K, V, L>,
+    }
+
+    impl<'a, K: 'a, V: 'a, L: SlotLayout<Key = K, Value = V>> Iterator for ValuesMut<'a, K, V, L> {
+        type Item = &'a mut V;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.inner.next().map(|(_, value)| value)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.inner.size_hint()
+        }
+    }
+
+    impl<'a, K: 'a, V: 'a, L: SlotLayout<Key = K, Value = V>> ExactSizeIterator
+        for ValuesMut<'a, K, V, L>
+    {
+    }
+
+    pub struct IntoIter<K, V, L = KeyValuePair<K, V>>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        table: Slots<L::Columns>,
+        old_table: Slots<L::Columns>,
+        /// Index into `table` followed by `old_table`.
+        index: usize,
+        remaining: usize,
+    }
+
+    impl<K, V, L: SlotLayout<Key = K, Value = V>> Iterator for IntoIter<K, V, L> {
+        type Item = (K, V);
+
+        fn next(&mut self) -> Option<Self::Item> {
+            while self.remaining > 0 {
+                let index = self.index;
+                self.index += 1;
+                let taken = match index.checked_sub(self.table.len()) {
+                    None => self.table.take(index),
+                    Some(old_index) => self.old_table.take(old_index),
+                };
+                if let Some((key, value, _)) = taken {
+                    self.remaining -= 1;
+                    return Some((key, value));
+                }
+            }
+            None
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.remaining, Some(self.remaining))
+        }
+    }
+
+    impl<K, V, L: SlotLayout<Key = K, Value = V>> ExactSizeIterator for IntoIter<K, V, L> {}
+
+    pub struct Drain<'a, K, V, L = KeyValuePair<K, V>>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        slots: &'a mut Slots<L::Columns>,
+        /// The table's entry count, kept in step with the slots.
+        num_entries: &'a mut usize,
+        /// Slot after the next one to take.
+        index: usize,
+    }
+
+    impl<K, V, L: SlotLayout<Key = K, Value = V>> Iterator for Drain<'_, K, V, L> {
+        type Item = (K, V);
+
+        fn next(&mut self) -> Option<Self::Item> {
+            while *self.num_entries > 0 {
+                self.index = (self.index + self.slots.len() - 1) & (self.slots.len() - 1);
+                if let Some((key, value, _)) = self.slots.take(self.index) {
+                    *self.num_entries -= 1;
+                    return Some((key, value));
+                }
+            }
+            None
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (*self.num_entries, Some(*self.num_entries))
+        }
+    }
+
+    impl<K, V, L: SlotLayout<Key = K, Value = V>> ExactSizeIterator for Drain<'_, K, V, L> {}
+
+    impl<K, V, L: SlotLayout<Key = K, Value = V>> Drop for Drain<'_, K, V, L> {
+        fn drop(&mut self) {
+            self.for_each(drop);
+        }
+    }
+
+    /// Removes entries matching a predicate; see
+    /// `RobinHoodHashTable::extract_if`.
+    pub struct ExtractIf<'a, K, V, F, S = RandomState, L = KeyValuePair<K, V>>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        table: &'a mut RobinHoodHashTable<L, S>,
+        /// Slot the sweep started at, the first of a run.
+        start: usize,
+        /// Slots handled so far, counting from `start`.
+        visited: usize,
+        /// Empty slots directly behind the next slot that the entries after
+        /// them may be shifted back into. Reset at the end of each run.
+        holes: usize,
+        extract: F,
+    }
+
+    impl<K, V, F, S, L> ExtractIf<'_, K, V, F, S, L>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        /// Advances the sweep to the next entry to extract. With `keep_rest`
+        /// every remaining entry is kept and the sweep ends as soon as no
+        /// entries are left to shift back. A slot counts as visited only once
+        /// it has been handled, so a panicking predicate leaves the sweep
+        /// where it can be finished.
+        fn sweep(&mut self, keep_rest: bool) -> Option<(K, V)> {
+            let slots = &mut self.table.table;
+            let mask = slots.len() - 1;
+            while self.visited < slots.len() && !(keep_rest && self.holes == 0) {
+                let index = (self.start + self.visited) & mask;
+                match slots.psl(index) {
+                    None => self.holes = 0,
+                    Some(psl) => {
+                        if !keep_rest {
+                            // SAFETY: the slot is occupied and the references
+                            // only live for the call.
+                            let (key, value) = unsafe { slots.get_unchecked_mut(index) };
+                            if (self.extract)(key, value) {
+                                let (key, value, _) = slots.take(index)?;
+                                self.table.num_entries -= 1;
+                                self.visited += 1;
+                                self.holes += 1;
+                                return Some((key, value));
+                            }
+                        }
+                        // The entry cannot move back past its home slot, which
+                        // leaves any holes before that empty for good.
+                        let distance = psl.min(self.holes);
+                        if distance > 0 {
+                            slots.shift_back(index, distance);
+                        }
+                        self.holes = distance;
+                    }
+                }
+                self.visited += 1;
+            }
+            None
+        }
+    }
+
+    impl<K, V, F, S, L> Iterator for ExtractIf<'_, K, V, F, S, L>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        type Item = (K, V);
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let extracted = self.sweep(false)?;
+            // Close the holes now rather than on drop, then step back over
+            // them so the entries shifted into them are still checked.
+            let holes = self.holes;
+            let visited = self.visited;
+            self.sweep(true);
+            self.visited = visited - holes;
+            self.holes = 0;
+            Some(extracted)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (0, Some(self.table.num_entries))
+        }
+    }
+
+    impl<K, V, F, S, L> Drop for ExtractIf<'_, K, V, F, S, L>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        fn drop(&mut self) {
+            self.sweep(true);
+            self.table.shrink_if_sparse();
+        }
+    }
+
+    impl<'a, K: 'a, V: 'a, S, L> IntoIterator for &'a RobinHoodHashTable<L, S>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        type Item = (&'a K, &'a V);
+        type IntoIter = Iter<'a, K, V, L>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter()
+        }
+    }
+
+    impl<'a, K: 'a, V: 'a, S, L> IntoIterator for &'a mut RobinHoodHashTable<L, S>
+    where
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        type Item = (&'a K, &'a mut V);
+        type IntoIter = IterMut<'a, K, V, L>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter_mut()
+        }
+    }
+
+    impl<K, V, S, L: SlotLayout<Key = K, Value = V>> IntoIterator for RobinHoodHashTable<L, S> {
+        type Item = (K, V);
+        type IntoIter = IntoIter<K, V, L>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            IntoIter {
+                table: self.table,
+                old_table: self.old_table,
+                index: 0,
+                remaining: self.num_entries,
+            }
+        }
+    }
+
+    impl<K, V, S, L> FromIterator<(K, V)> for RobinHoodHashTable<L, S>
+    where
+        K: Hash + Eq,
+        S: BuildHasher + Default,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+            let mut rht = Self::default();
+            rht.extend(iter);
+            rht
+        }
+    }
+
+    impl<K, V, S, L> Extend<(K, V)> for RobinHoodHashTable<L, S>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+            let iter = iter.into_iter();
+            self.reserve(iter.size_hint().0);
+            for (key, value) in iter {
+                self.insert(key, value);
+            }
+        }
+    }
+
+    /// Implements `Extend<(&K, &V)>` for a layout by copying each pair. It is
+    /// written per layout, as a blanket impl over `L` would overlap with
+    /// `Extend<(K, V)>` when the key type is itself a reference.
+    macro_rules! extend_by_copy {
+        ($layout:ident) => {
+            impl<'a, K, V, S> Extend<(&'a K, &'a V)> for RobinHoodHashTable<$layout<K, V>, S>
+            where
+                K: Hash + Eq + Copy + 'a,
+                V: Copy + 'a,
+                S: BuildHasher,
+            {
+                fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+                    self.extend(iter.into_iter().map(|(key, value)| (*key, *value)));
+                }
+            }
+        };
+    }
+
+    extend_by_copy!(KeyValuePair);
+    extend_by_copy!(SplitKeyValue);
+
+    impl<K: Hash + Eq, V, const N: usize> From<[(K, V); N]> for RobinHoodHashTable<KeyValuePair<K, V>> {
+        fn from(entries: [(K, V); N]) -> Self {
+            Self::from_iter(entries)
+        }
+    }
+
+    impl<K, V, S, L, Q> Index<&Q> for RobinHoodHashTable<L, S>
+    where
+        K: Hash + Eq + Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        S: BuildHasher,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        type Output = V;
+
+        /// Returns the value stored for `key`.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `key` is not in the table.
+        fn index(&self, key: &Q) -> &V {
+            self.get(key).expect("key not found in RobinHoodHashTable")
+        }
+    }
+
+    /// Two tables are equal when they hold the same entries, regardless of
+    /// capacity or where the entries were placed.
+    impl<K, V, S, L> PartialEq for RobinHoodHashTable<L, S>
+    where
+        K: Hash + Eq,
+        V: PartialEq,
+        S: BuildHasher,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        fn eq(&self, other: &Self) -> bool {
+            self.len() == other.len()
+                && self
+                    .iter()
+                    .all(|(key, value)| other.get(key).is_some_and(|other| *value == *other))
+        }
+    }
+
+    impl<K, V, S, L> Eq for RobinHoodHashTable<L, S>
+    where
+        K: Hash + Eq,
+        V: Eq,
+        S: BuildHasher,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+    }
+
+    impl<K, V, S, L> fmt::Debug for RobinHoodHashTable<L, S>
+    where
+        K: fmt::Debug,
+        V: fmt::Debug,
+        L: SlotLayout<Key = K, Value = V>,
+    {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_map().entries(self.iter()).finish()
+        }
+    }
+
+    /// A hash set stored in a Robin Hood table with `()` values, so it gets the
+    /// same probing, resizing and layout as `RobinHoodHashTable`.
+    pub struct RobinHoodHashSet<T, S = RandomState> {
+        map: RobinHoodHashTable<KeyValuePair<T, ()>, S>,
+    }
+
+    impl<T: Clone, S: Clone> Clone for RobinHoodHashSet<T, S> {
+        fn clone(&self) -> Self {
+            RobinHoodHashSet {
+                map: self.map.clone(),
+            }
+        }
+    }
+
+    impl<T: Hash + Eq> RobinHoodHashSet<T> {
+        pub fn new() -> Self {
+            Self::with_capacity(DEFAULT_CAPACITY)
+        }
+
+        pub fn with_capacity(capacity: usize) -> Self {
+            Self::with_capacity_and_hasher(capacity, RandomState::new())
+        }
+    }
+
+    impl<T: Hash + Eq, S: BuildHasher + Default> Default for RobinHoodHashSet<T, S> {
+        fn default() -> Self {
+            Self::with_hasher(S::default())
+        }
+    }
+
+    impl<T, S> RobinHoodHashSet<T, S> {
+        pub fn len(&self) -> usize {
+            self.map.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.map.is_empty()
+        }
+
+        pub fn capacity(&self) -> usize {
+            self.map.capacity()
+        }
+
+        pub fn iter(&self) -> SetIter<'_, T> {
+            SetIter {
+                inner: self.map.keys(),
+            }
+        }
+
+        /// Removes every value, yielding them. The capacity is kept.
+        pub fn drain(&mut self) -> SetDrain<'_, T> {
+            SetDrain {
+                inner: self.map.drain(),
+            }
+        }
+
+        /// Removes every value, keeping the allocated capacity.
+        pub fn clear(&mut self) {
+            self.map.clear();
+        }
+    }
+
+    impl<T: Hash + Eq, S: BuildHasher> RobinHoodHashSet<T, S> {
+        pub fn with_hasher(hasher_state: S) -> Self {
+            Self::with_capacity_and_hasher(DEFAULT_CAPACITY, hasher_state)
+        }
+
+        pub fn with_capacity_and_hasher(capacity: usize, hasher_state: S) -> Self {
+            RobinHoodHashSet {
+                map: RobinHoodHashTable::with_capacity_and_hasher(capacity, hasher_state),
+            }
+        }
+
+        pub fn hasher(&self) -> &S {
+            self.map.hasher()
+        }
+
+        pub fn reserve(&mut self, additional: usize) {
+            self.map.reserve(additional);
+        }
+
+        pub fn shrink_to_fit(&mut self) {
+            self.map.shrink_to_fit();
+        }
+
+        /// Adds `value`, returning `true` if it was not already present. An
+        /// equal value already in the set is kept.
+        pub fn insert(&mut self, value: T) -> bool {
+            match self.map.entry(value) {
+                Entry::Occupied(..) => false,
+                Entry::Vacant(entry) => {
+                    entry.insert(());
+                    true
+                }
+            }
+        }
+
+        /// Adds `value`, replacing and returning an equal value already in
+        /// the set.
+        pub fn replace(&mut self, value: T) -> Option<T> {
+            match self.map.entry(value) {
+                Entry::Occupied(entry) => Some(entry.replace_key()),
+                Entry::Vacant(entry) => {
+                    entry.insert(());
+                    None
+                }
+            }
+        }
+
+        pub fn contains<Q>(&self, value: &Q) -> bool
+        where
+            T: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            self.map.contains(value)
+        }
+
+        /// Returns the stored value equal to `value`, if any.
+        pub fn get<Q>(&self, value: &Q) -> Option<&T>
+        where
+            T: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            self.map.get_key_value(value).map(|(value, _)| value)
+        }
+
+        /// Removes `value`, returning `true` if it was present.
+        pub fn remove<Q>(&mut self, value: &Q) -> bool
+        where
+            T: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            self.map.remove(value).is_some()
+        }
+
+        /// Removes and returns the stored value equal to `value`, if any.
+        pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+        where
+            T: Borrow<Q>,
+            Q: Hash + Eq + ?Sized,
+        {
+            self.map.remove_entry(value).map(|(value, _)| value)
+        }
+
+        /// Visits the values in `self` or `other`, each once.
+        pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, T, S> {
+            let (larger, smaller) = if self.len() >= other.len() {
+                (self, other)
+            } else {
+                (other, self)
+            };
+            Union {
+                inner: larger.iter().chain(smaller.difference(larger)),
+            }
+        }
+
+        /// Visits the values in both `self` and `other`, walking the smaller
+        /// of the two.
+        pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, T, S> {
+            let (smaller, larger) = if self.len() <= other.len() {
+                (self, other)
+            } else {
+                (other, self)
+            };
+            Intersection {
+                iter: smaller.iter(),
+                other: larger,
+            }
+        }
+
+        /// Visits the values in `self` that are not in `other`.
+        pub fn difference<'a>(&'a self, other: &'a Self) -> Difference<'a, T, S> {
+            Difference {
+                iter: self.iter(),
+                other,
+            }
+        }
+
+        /// Visits the values in exactly one of `self` and `other`.
+        pub fn symmetric_difference<'a>(
+            &'a self,
+            other: &'a Self,
+        ) -> SymmetricDifference<'a, T, S> {
+            SymmetricDifference {
+                inner: self.difference(other).chain(other.difference(self)),
+            }
+        }
+
+        pub fn is_disjoint(&self, other: &Self) -> bool {
+            self.intersection(other).next().is_none()
+        }
+
+        pub fn is_subset(&self, other: &Self) -> bool {
+            self.len() <= other.len() && self.iter().all(|value| other.contains(value))
+        }
+
+        pub fn is_superset(&self, other: &Self) -> bool {
+            other.is_subset(self)
+        }
+    }
+
+    impl<T: Hash + Eq, S: BuildHasher + Default> FromIterator<T> for RobinHoodHashSet<T, S> {
+        fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+            let mut set = Self::default();
+            set.extend(iter);
+            set
+        }
+    }
+
+    impl<T: Hash + Eq, S: BuildHasher> Extend<T> for RobinHoodHashSet<T, S> {
+        fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+            let iter = iter.into_iter();
+            self.reserve(iter.size_hint().0);
+            for value in iter {
+                self.insert(value);
+            }
+        }
+    }
+
+    impl<'a, T: Hash + Eq + Copy + 'a, S: BuildHasher> Extend<&'a T> for RobinHoodHashSet<T, S> {
+        fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+            self.extend(iter.into_iter().copied());
+        }
+    }
+
+    impl<T: Hash + Eq, const N: usize> From<[T; N]> for RobinHoodHashSet<T> {
+        fn from(values: [T; N]) -> Self {
+            Self::from_iter(values)
+        }
+    }
+
+    impl<T: Hash + Eq, S: BuildHasher> PartialEq for RobinHoodHashSet<T, S> {
+        fn eq(&self, other: &Self) -> bool {
+            self.len() == other.len() && self.is_subset(other)
+        }
+    }
+
+    impl<T: Hash + Eq, S: BuildHasher> Eq for RobinHoodHashSet<T, S> {}
+
+    impl<T: fmt::Debug, S> fmt::Debug for RobinHoodHashSet<T, S> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_set().entries(self.iter()).finish()
+        }
+    }
+
+    /// Implements a set operator on references by collecting the matching
+    /// lazy iterator into a new set, e.g. `&a | &b`.
+    macro_rules! set_operator {
+        ($trait:ident, $method:ident, $iter:ident) => {
+            impl<T, S> $trait<&RobinHoodHashSet<T, S>> for &RobinHoodHashSet<T, S>
+            where
+                T: Hash + Eq + Clone,
+                S: BuildHasher + Default,
+            {
+                type Output = RobinHoodHashSet<T, S>;
+
+                fn $method(self, rhs: &RobinHoodHashSet<T, S>) -> RobinHoodHashSet<T, S> {
+                    self.$iter(rhs).cloned().collect()
+                }
+            }
+        };
+    }
+
+    set_operator!(BitOr, bitor, union);
+    set_operator!(BitAnd, bitand, intersection);
+    set_operator!(Sub, sub, difference);
+    set_operator!(BitXor, bitxor, symmetric_difference);
+
+    pub struct SetIter<'a, T: 'a> {
+        inner: Keys<'a, T, ()>,
+    }
+
+    impl<'a, T> Iterator for SetIter<'a, T> {
+        type Item = &'a T;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.inner.next()
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.inner.size_hint()
+        }
+    }
+
+    impl<T> ExactSizeIterator for SetIter<'_, T> {}
+
+    impl<T> Clone for SetIter<'_, T> {
+        fn clone(&self) -> Self {
+            SetIter {
+                inner: Keys {
+                    inner: self.inner.inner.clone(),
+                },
+            }
+        }
+    }
+
+    pub struct SetIntoIter<T> {
+        inner: IntoIter<T, ()>,
+    }
+
+    impl<T> Iterator for SetIntoIter<T> {
+        type Item = T;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.inner.next().map(|(value, _)| value)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.inner.size_hint()
+        }
+    }
+
+    impl<T> ExactSizeIterator for SetIntoIter<T> {}
+
+    pub struct SetDrain<'a, T: 'a> {
+        inner: Drain<'a, T, ()>,
+    }
+
+    impl<T> Iterator for SetDrain<'_, T> {
+        type Item = T;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.inner.next().map(|(value, _)| value)
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            self.inner.size_hint()
+        }
+    }
+
+    impl<T> ExactSizeIterator for SetDrain<'_, T> {}
+
+    pub struct Union<'a, T: 'a, S> {
+        inner: std::iter::Chain<SetIter<'a, T>, Difference<'a, T, S>>,
+    }
+
+    impl<'a, T: Hash + Eq, S: BuildHasher> Iterator for Union<'a, T, S> {
+        type Item = &'a T;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.inner.next()
+        }
+    }
+
+    pub struct Intersection<'a, T: 'a, S> {
+        iter: SetIter<'a, T>,
+        other: &'a RobinHoodHashSet<T, S>,
+    }
+
+    impl<'a, T: Hash + Eq, S: BuildHasher> Iterator for Intersection<'a, T, S> {
+        type Item = &'a T;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let other = self.other;
+            self.iter.find(|value| other.contains(*value))
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (0, self.iter.size_hint().1)
+        }
+    }
+
+    pub struct Difference<'a, T: 'a, S> {
+        iter: SetIter<'a, T>,
+        other: &'a RobinHoodHashSet<T, S>,
+    }
+
+    impl<'a, T: Hash + Eq, S: BuildHasher> Iterator for Difference<'a, T, S> {
+        type Item = &'a T;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let other = self.other;
+            self.iter.find(|value| !other.contains(*value))
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (0, self.iter.size_hint().1)
+        }
+    }
+
+    pub struct SymmetricDifference<'a, T: 'a, S> {
+        inner: std::iter::Chain<Difference<'a, T, S>, Difference<'a, T, S>>,
+    }
+
+    impl<'a, T: Hash + Eq, S: BuildHasher> Iterator for SymmetricDifference<'a, T, S> {
+        type Item = &'a T;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.inner.next()
+        }
+    }
+
+    impl<'a, T, S> IntoIterator for &'a RobinHoodHashSet<T, S> {
+        type Item = &'a T;
+        type IntoIter = SetIter<'a, T>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            self.iter()
+        }
+    }
+
+    impl<T, S> IntoIterator for RobinHoodHashSet<T, S> {
+        type Item = T;
+        type IntoIter = SetIntoIter<T>;
+
+        fn into_iter(self) -> Self::IntoIter {
+            SetIntoIter {
+                inner: self.map.into_iter(),
+            }
+        }
+    }
+
+    /// A `BuildHasher` for SipHash-2-4 keyed from a single `u64` seed. Unlike
+    /// `RandomState` its seed can be read back and reused, so tables that use
+    /// it can be snapshotted and loaded again.
+    #[derive(Clone)]
+    pub struct KeyedState {
+        seed: u64,
+    }
+
+    impl KeyedState {
+        /// Creates a state with a random seed.
+        pub fn new() -> Self {
+            Self::with_seed(RandomState::new().hash_one(0u8))
+        }
+
+        pub fn with_seed(seed: u64) -> Self {
+            KeyedState { seed }
+        }
+
+        pub fn seed(&self) -> u64 {
+            self.seed
+        }
+    }
+
+    impl Default for KeyedState {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    /// The seed is left out so it does not end up in logs.
+    impl fmt::Debug for KeyedState {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("KeyedState").finish_non_exhaustive()
+        }
+    }
+
+    impl BuildHasher for KeyedState {
+        type Hasher = KeyedHasher;
+
+        fn build_hasher(&self) -> KeyedHasher {
+            // The second key is a bijection of the seed, so distinct seeds
+            // give distinct key pairs.
+            KeyedHasher::new_with_keys(self.seed, self.seed.wrapping_mul(FIBONACCI_MULTIPLIER))
+        }
+    }
+
+    /// SipHash-2-4, built by `KeyedState`.
+    #[derive(Clone, Debug)]
+    pub struct KeyedHasher {
+        v0: u64,
+        v1: u64,
+        v2: u64,
+        v3: u64,
+        /// Bytes written since the last full word, lowest first.
+        tail: u64,
+        tail_len: usize,
+        length: usize,
+    }
+
+    impl KeyedHasher {
+        fn new_with_keys(k0: u64, k1: u64) -> Self {
+            KeyedHasher {
+                v0: k0 ^ 0x736f_6d65_7073_6575,
+                v1: k1 ^ 0x646f_7261_6e64_6f6d,
+                v2: k0 ^ 0x6c79_6765_6e65_7261,
+                v3: k1 ^ 0x7465_6462_7974_6573,
+                tail: 0,
+                tail_len: 0,
+                length: 0,
+            }
+        }
+
+        fn round(&mut self) {
+            self.v0 = self.v0.wrapping_add(self.v1);
+            self.v1 = self.v1.rotate_left(13) ^ self.v0;
+            self.v0 = self.v0.rotate_left(32);
+            self.v2 = self.v2.wrapping_add(self.v3);
+            self.v3 = self.v3.rotate_left(16) ^ self.v2;
+            self.v0 = self.v0.wrapping_add(self.v3);
+            self.v3 = self.v3.rotate_left(21) ^ self.v0;
+            self.v2 = self.v2.wrapping_add(self.v1);
+            self.v1 = self.v1.rotate_left(17) ^ self.v2;
+            self.v2 = self.v2.rotate_left(32);
+        }
+
+        fn compress(&mut self, word: u64) {
+            self.v3 ^= word;
+            self.round();
+            self.round();
+            self.v0 ^= word;
+        }
+    }
+
+    impl Hasher for KeyedHasher {
+        fn write(&mut self, bytes: &[u8]) {
+            self.length += bytes.len();
+            let mut bytes = bytes;
+            while !bytes.is_empty() {
+                if self.tail_len == 0 && bytes.len() >= 8 {
+                    let (word, rest) = bytes.split_at(8);
+                    self.compress(u64::from_le_bytes(word.try_into().unwrap()));
+                    bytes = rest;
+                    continue;
+                }
+                self.tail |= u64::from(bytes[0]) << (8 * self.tail_len);
+                self.tail_len += 1;
+                bytes = &bytes[1..];
+                if self.tail_len == 8 {
+                    let word = mem::replace(&mut self.tail, 0);
+                    self.tail_len = 0;
+                    self.compress(word);
+                }
+            }
+        }
+
+        fn finish(&self) -> u64 {
+            let mut state = self.clone();
+            let last = ((self.length as u64 & 0xff) << 56) | self.tail;
+            state.compress(last);
+            state.v2 ^= 0xff;
+            for _ in 0..4 {
+                state.round();
+            }
+            state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+        }
+    }
+
+    /// Binary snapshots that load without rehashing; see
+    /// `RobinHoodHashTable::write_snapshot` for the format.
+    mod snapshot {
+        use super::{
+            encode_psl, home_slot, Columns, KeyedState, RobinHoodHashTable, SlotLayout, Slots,
+            TryReserveError, EMPTY,
+        };
+        use std::convert::TryFrom;
+        use std::error::Error;
+        use std::fmt;
+        use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+        use std::io::{self, Read, Write};
+
+        const MAGIC: [u8; 4] = *b"RHHT";
+        const VERSION: u16 = 1;
+        const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+        const FNV_PRIME: u64 = 0x0100_0000_01B3;
+        /// Loaded keys rehashed to check the hasher reproduces the snapshot.
+        const HASHER_CHECKS: usize = 8;
+
+        /// Writes a key or value into a snapshot.
+        pub trait Encode {
+            fn encode<W: Write>(&self, out: &mut W) -> io::Result<()>;
+        }
+
+        /// Reads back what the matching `Encode` impl wrote.
+        pub trait Decode: Sized {
+            fn decode<R: Read>(input: &mut R) -> io::Result<Self>;
+        }
+
+        /// A hasher that can be rebuilt from a seed, so a loaded table hashes
+        /// lookups the same way the snapshotted one placed its entries.
+        /// `RandomState` cannot expose its keys and so cannot be snapshotted;
+        /// use `KeyedState` for a keyed hasher that can.
+        pub trait SnapshotHasher: BuildHasher + Sized {
+            fn seed(&self) -> u64;
+            fn from_seed(seed: u64) -> Self;
+        }
+
+        impl SnapshotHasher for KeyedState {
+            fn seed(&self) -> u64 {
+                KeyedState::seed(self)
+            }
+
+            fn from_seed(seed: u64) -> Self {
+                KeyedState::with_seed(seed)
+            }
+        }
+
+        /// Unseeded hashers always hash the same way; the seed is zero.
+        impl<H: Default + Hasher> SnapshotHasher for BuildHasherDefault<H> {
+            fn seed(&self) -> u64 {
+                0
+            }
+
+            fn from_seed(_seed: u64) -> Self {
+                BuildHasherDefault::default()
+            }
+        }
+
+        /// The error returned by `RobinHoodHashTable::read_snapshot`.
+        #[derive(Debug)]
+        pub enum SnapshotError {
+            Io(io::Error),
+            /// The input does not start with the snapshot magic.
+            BadMagic,
+            UnsupportedVersion(u16),
+            /// The header or body does not match its checksum.
+            ChecksumMismatch,
+            /// The snapshot is well-formed but describes an impossible table.
+            Corrupt(&'static str),
+            /// The rebuilt hasher hashes keys differently than the one that
+            /// wrote the snapshot.
+            HasherMismatch,
+            Allocation(TryReserveError),
+        }
+
+        impl fmt::Display for SnapshotError {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    SnapshotError::Io(err) => write!(f, "failed to read snapshot: {}", err),
+                    SnapshotError::BadMagic => write!(f, "input is not a table snapshot"),
+                    SnapshotError::UnsupportedVersion(version) => {
+                        write!(f, "unsupported snapshot version {}", version)
+                    }
+                    SnapshotError::ChecksumMismatch => write!(f, "snapshot checksum mismatch"),
+                    SnapshotError::Corrupt(reason) => write!(f, "corrupt snapshot: {}", reason),
+                    SnapshotError::HasherMismatch => {
+                        write!(f, "hasher does not reproduce the snapshot's hashes")
+                    }
+                    SnapshotError::Allocation(err) => write!(f, "{}", err),
+                }
+            }
+        }
+
+        impl Error for SnapshotError {
+            fn source(&self) -> Option<&(dyn Error + 'static)> {
+                match self {
+                    SnapshotError::Io(err) => Some(err),
+                    SnapshotError::Allocation(err) => Some(err),
+                    _ => None,
+                }
+            }
+        }
+
+        impl From<io::Error> for SnapshotError {
+            fn from(err: io::Error) -> Self {
+                SnapshotError::Io(err)
+            }
+        }
+
+        impl From<TryReserveError> for SnapshotError {
+            fn from(err: TryReserveError) -> Self {
+                SnapshotError::Allocation(err)
+            }
+        }
+
+        macro_rules! encode_le_bytes {
+            ($($int:ty),*) => {
+                $(
+                    impl Encode for $int {
+                        fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+                            out.write_all(&self.to_le_bytes())
+                        }
+                    }
+
+                    impl Decode for $int {
+                        fn decode<R: Read>(input: &mut R) -> io::Result<Self> {
+                            let mut bytes = [0; std::mem::size_of::<$int>()];
+                            input.read_exact(&mut bytes)?;
+                            Ok(<$int>::from_le_bytes(bytes))
+                        }
+                    }
+                )*
+            };
+        }
+
+        encode_le_bytes!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+        impl Encode for () {
+            fn encode<W: Write>(&self, _out: &mut W) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl Decode for () {
+            fn decode<R: Read>(_input: &mut R) -> io::Result<Self> {
+                Ok(())
+            }
+        }
+
+        impl Encode for bool {
+            fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+                u8::from(*self).encode(out)
+            }
+        }
+
+        impl Decode for bool {
+            fn decode<R: Read>(input: &mut R) -> io::Result<Self> {
+                match u8::decode(input)? {
+                    0 => Ok(false),
+                    1 => Ok(true),
+                    _ => Err(invalid_data("bool is neither 0 nor 1")),
+                }
+            }
+        }
+
+        /// Lengths are `u64`, so snapshots do not depend on the pointer width.
+        fn encode_len<W: Write>(len: usize, out: &mut W) -> io::Result<()> {
+            (len as u64).encode(out)
+        }
+
+        fn decode_len<R: Read>(input: &mut R) -> io::Result<usize> {
+            usize::try_from(u64::decode(input)?).map_err(|_| invalid_data("length overflows usize"))
+        }
+
+        fn invalid_data(reason: &'static str) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, reason)
+        }
+
+        impl Encode for String {
+            fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+                encode_len(self.len(), out)?;
+                out.write_all(self.as_bytes())
+            }
+        }
+
+        impl Decode for String {
+            fn decode<R: Read>(input: &mut R) -> io::Result<Self> {
+                String::from_utf8(Vec::<u8>::decode(input)?)
+                    .map_err(|_| invalid_data("string is not UTF-8"))
+            }
+        }
+
+        impl<T: Encode> Encode for Vec<T> {
+            fn encode<W: Write>(&self, out: &mut W) -> io::Result<()> {
+                encode_len(self.len(), out)?;
+                self.iter().try_for_each(|item| item.encode(out))
+            }
+        }
+
+        impl<T: Decode> Decode for Vec<T> {
+            fn decode<R: Read>(input: &mut R) -> io::Result<Self> {
+                let len = decode_len(input)?;
+                // Grow as items arrive, so a corrupt length cannot allocate
+                // more than the input holds.
+                let mut items = Vec::with_capacity(len.min(4096));
+                for _ in 0..len {
+                    items.push(T::decode(input)?);
+                }
+                Ok(items)
+            }
+        }
+
+        /// Runs FNV-1a over every byte written or read through it.
+        struct Checksum<T> {
+            inner: T,
+            state: u64,
+        }
+
+        impl<T> Checksum<T> {
+            fn new(inner: T) -> Self {
+                Checksum {
+                    inner,
+                    state: FNV_OFFSET_BASIS,
+                }
+            }
+
+            fn update(&mut self, bytes: &[u8]) {
+                for byte in bytes {
+                    self.state = (self.state ^ u64::from(*byte)).wrapping_mul(FNV_PRIME);
+                }
+            }
+        }
+
+        impl<W: Write> Write for Checksum<W> {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                let written = self.inner.write(buf)?;
+                self.update(&buf[..written]);
+                Ok(written)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.inner.flush()
+            }
+        }
+
+        impl<R: Read> Read for Checksum<R> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let read = self.inner.read(buf)?;
+                self.update(&buf[..read]);
+                Ok(read)
+            }
+        }
+
+        impl<K, V, S, L> RobinHoodHashTable<L, S>
+        where
+            K: Hash + Eq + Encode + Decode,
+            V: Encode + Decode,
+            S: SnapshotHasher,
+            L: SlotLayout<Key = K, Value = V>,
+        {
+            /// Writes the table as a snapshot for `read_snapshot`. Slots are
+            /// stored in their placed positions along with their cached
+            /// hashes, so a load copies them straight back. Writes are not
+            /// buffered; pass a `BufWriter` for files and sockets. All integers
+            /// are little-endian; the header is:
+            ///
+            /// | field           | type                                  |
+            /// |-----------------|---------------------------------------|
+            /// | magic           | `b"RHHT"`                             |
+            /// | version         | `u16`, currently 1                    |
+            /// | slot count      | `u64`, a power of two                 |
+            /// | len             | `u64`                                 |
+            /// | max load factor | `f64`                                 |
+            /// | hasher seed     | `u64`, from `SnapshotHasher::seed`    |
+            /// | header checksum | `u64`, FNV-1a of the fields above     |
+            ///
+            /// The body holds, for each slot, its `u16` metadata followed, if
+            /// it is occupied, by its `u32` hash, key and value. Entries still
+            /// waiting in the old slots of an incremental resize come after the
+            /// slots as a `u64` count and hash, key, value triples, and are
+            /// placed by their hash. A trailing `u64` holds the FNV-1a of
+            /// everything before it, header included.
+            pub fn write_snapshot<W: Write>(&self, out: W) -> io::Result<()> {
+                let mut out = Checksum::new(out);
+                out.write_all(&MAGIC)?;
+                VERSION.encode(&mut out)?;
+                encode_len(self.table.len(), &mut out)?;
+                encode_len(self.num_entries, &mut out)?;
+                self.max_load_factor.encode(&mut out)?;
+                self.hasher_state.seed().encode(&mut out)?;
+                let header_checksum = out.state;
+                header_checksum.encode(&mut out)?;
+                self.write_snapshot_body(&mut out)?;
+                let checksum = out.state;
+                checksum.encode(&mut out.inner)?;
+                out.flush()
+            }
+
+            fn write_snapshot_body<W: Write>(&self, out: &mut W) -> io::Result<()> {
+                for index in 0..self.table.len() {
+                    self.table.meta[index].encode(out)?;
+                    if let Some((key, value)) = self.table.get(index) {
+                        self.table.hash(index).encode(out)?;
+                        key.encode(out)?;
+                        value.encode(out)?;
+                    }
+                }
+                let pending = (0..self.old_table.len())
+                    .filter(|&index| self.old_table.is_occupied(index))
+                    .count();
+                encode_len(pending, out)?;
+                for index in 0..self.old_table.len() {
+                    if let Some((key, value)) = self.old_table.get(index) {
+                        self.old_table.hash(index).encode(out)?;
+                        key.encode(out)?;
+                        value.encode(out)?;
+                    }
+                }
+                Ok(())
+            }
+
+            /// Loads a table written by `write_snapshot`, copying each entry
+            /// back into the slot it was placed in without rehashing. Settings
+            /// other than the max load factor and hasher are the defaults.
+            /// Exactly the snapshot's bytes are read, so whatever follows it is
+            /// left in `input`. Reads are not buffered; pass a `BufReader` for
+            /// files and sockets.
+            pub fn read_snapshot<R: Read>(input: R) -> Result<Self, SnapshotError> {
+                let mut input = Checksum::new(input);
+                let mut magic = [0; 4];
+                input.read_exact(&mut magic)?;
+                if magic != MAGIC {
+                    return Err(SnapshotError::BadMagic);
+                }
+                let version = u16::decode(&mut input)?;
+                if version != VERSION {
+                    return Err(SnapshotError::UnsupportedVersion(version));
+                }
+                let capacity = decode_len(&mut input)?;
+                let len = decode_len(&mut input)?;
+                let max_load_factor = f64::decode(&mut input)?;
+                let seed = u64::decode(&mut input)?;
+                // Checked before the header is trusted with an allocation.
+                let header_checksum = input.state;
+                if u64::decode(&mut input)? != header_checksum {
+                    return Err(SnapshotError::ChecksumMismatch);
+                }
+                if capacity == 0 || !capacity.is_power_of_two() {
+                    return Err(SnapshotError::Corrupt("slot count is not a power of two"));
+                }
+                if !(max_load_factor > 0.0 && max_load_factor <= 1.0) {
+                    return Err(SnapshotError::Corrupt("max load factor is not in (0, 1]"));
+                }
+                // A table always grows before reaching its max load factor,
+                // so every run ends at an empty slot.
+                if len as f64 / capacity as f64 >= max_load_factor {
+                    return Err(SnapshotError::Corrupt("entries reach the max load factor"));
+                }
+
+                let mut slots = Slots::<L::Columns>::try_with_capacity(capacity)?;
+                let mut occupied = 0;
+                for index in 0..capacity {
+                    let meta = u16::decode(&mut input)?;
+                    if meta == EMPTY {
+                        continue;
+                    }
+                    let hash = u32::decode(&mut input)?;
+                    let key = K::decode(&mut input)?;
+                    let value = V::decode(&mut input)?;
+                    if occupied == len {
+                        return Err(SnapshotError::Corrupt("more entries than the header says"));
+                    }
+                    let distance = index.wrapping_sub(home_slot(hash, capacity)) & (capacity - 1);
+                    if meta != encode_psl(distance) {
+                        return Err(SnapshotError::Corrupt("entry is not at its probe distance"));
+                    }
+                    slots.hashes[index] = hash;
+                    slots.columns.write(index, key, value);
+                    slots.meta[index] = meta;
+                    occupied += 1;
+                }
+                let pending = decode_len(&mut input)?;
+                if pending != len - occupied {
+                    return Err(SnapshotError::Corrupt(
+                        "entry count does not match the header",
+                    ));
+                }
+                for _ in 0..pending {
+                    let hash = u32::decode(&mut input)?;
+                    let key = K::decode(&mut input)?;
+                    let value = V::decode(&mut input)?;
+                    slots.place(home_slot(hash, capacity), 0, hash, key, value);
+                }
+                let checksum = input.state;
+                if u64::decode(&mut input.inner)? != checksum {
+                    return Err(SnapshotError::ChecksumMismatch);
+                }
+
+                let mut rht = Self::try_from_parts(max_load_factor, 1, S::from_seed(seed))?;
+                let hasher_agrees = (0..capacity)
+                    .filter_map(|index| Some((slots.get(index)?.0, slots.hash(index))))
+                    .take(HASHER_CHECKS)
+                    .all(|(key, hash)| rht.hash_key(key) == hash);
+                if !hasher_agrees {
+                    return Err(SnapshotError::HasherMismatch);
+                }
+                rht.capacity = capacity;
+                rht.num_entries = len;
+                rht.table = slots;
+                Ok(rht)
+            }
+        }
+    }
+
+    pub use snapshot::{Decode, Encode, SnapshotError, SnapshotHasher};
+
+    /// `Serialize`/`Deserialize` for the table, as a map, and the set, as a
+    /// sequence. Deserializing pre-sizes from the length hint and keeps the
+    /// last of any duplicate keys; wrap the target in `Strict` to reject
+    /// duplicates instead.
+    #[cfg(feature = "serde")]
+    mod serde_impls {
+        use super::{RobinHoodHashSet, RobinHoodHashTable, SlotLayout};
+        use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+        use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+        use std::fmt;
+        use std::hash::{BuildHasher, Hash};
+        use std::marker::PhantomData;
+        use std::mem;
+
+        /// Caps pre-sizing at about a megabyte of entries, so a bogus length
+        /// hint cannot make us allocate more than the input could fill.
+        fn presize<T>(hint: Option<usize>) -> usize {
+            const MAX_PRESIZE_BYTES: usize = 1 << 20;
+            let cap = MAX_PRESIZE_BYTES / mem::size_of::<T>().max(1);
+            hint.unwrap_or(0).min(cap)
+        }
+
+        /// Deserializes a table or set, failing on a repeated key instead of
+        /// letting the later entry win. Use it as the target type, e.g.
+        /// `Strict<RobinHoodHashTable<KeyValuePair<K, V>>>`, then unwrap it.
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        pub struct Strict<T>(pub T);
+
+        impl<K, V, S, L> Serialize for RobinHoodHashTable<L, S>
+        where
+            K: Serialize,
+            V: Serialize,
+            L: SlotLayout<Key = K, Value = V>,
+        {
+            fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+                let mut map = serializer.serialize_map(Some(self.len()))?;
+                for (key, value) in self {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+
+        struct TableVisitor<L: SlotLayout, S> {
+            strict: bool,
+            marker: PhantomData<fn() -> RobinHoodHashTable<L, S>>,
+        }
+
+        impl<'de, K, V, S, L> Visitor<'de> for TableVisitor<L, S>
+        where
+            K: Deserialize<'de> + Hash + Eq,
+            V: Deserialize<'de>,
+            S: BuildHasher + Default,
+            L: SlotLayout<Key = K, Value = V>,
+        {
+            type Value = RobinHoodHashTable<L, S>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a map")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+                let mut rht = RobinHoodHashTable::default();
+                rht.reserve(presize::<(K, V)>(access.size_hint()));
+                while let Some((key, value)) = access.next_entry()? {
+                    if rht.insert(key, value).is_some() && self.strict {
+                        return Err(de::Error::custom("duplicate key in map"));
+                    }
+                }
+                Ok(rht)
+            }
+        }
+
+        impl<'de, K, V, S, L> Deserialize<'de> for RobinHoodHashTable<L, S>
+        where
+            K: Deserialize<'de> + Hash + Eq,
+            V: Deserialize<'de>,
+            S: BuildHasher + Default,
+            L: SlotLayout<Key = K, Value = V>,
+        {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_map(TableVisitor {
+                    strict: false,
+                    marker: PhantomData,
+                })
+            }
+        }
+
+        impl<'de, K, V, S, L> Deserialize<'de> for Strict<RobinHoodHashTable<L, S>>
+        where
+            K: Deserialize<'de> + Hash + Eq,
+            V: Deserialize<'de>,
+            S: BuildHasher + Default,
+            L: SlotLayout<Key = K, Value = V>,
+        {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer
+                    .deserialize_map(TableVisitor {
+                        strict: true,
+                        marker: PhantomData,
+                    })
+                    .map(Strict)
+            }
+        }
+
+        impl<T: Serialize, S> Serialize for RobinHoodHashSet<T, S> {
+            fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+                let mut seq = serializer.serialize_seq(Some(self.len()))?;
+                for value in self {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+        }
+
+        struct SetVisitor<T, S> {
+            strict: bool,
+            marker: PhantomData<fn() -> RobinHoodHashSet<T, S>>,
+        }
+
+        impl<'de, T, S> Visitor<'de> for SetVisitor<T, S>
+        where
+            T: Deserialize<'de> + Hash + Eq,
+            S: BuildHasher + Default,
+        {
+            type Value = RobinHoodHashSet<T, S>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a sequence")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+                let mut set = RobinHoodHashSet::default();
+                set.reserve(presize::<T>(access.size_hint()));
+                while let Some(value) = access.next_element()? {
+                    if !set.insert(value) && self.strict {
+                        return Err(de::Error::custom("duplicate value in set"));
+                    }
+                }
+                Ok(set)
+            }
+        }
+
+        impl<'de, T, S> Deserialize<'de> for RobinHoodHashSet<T, S>
+        where
+            T: Deserialize<'de> + Hash + Eq,
+            S: BuildHasher + Default,
+        {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_seq(SetVisitor {
+                    strict: false,
+                    marker: PhantomData,
+                })
+            }
+        }
+
+        impl<'de, T, S> Deserialize<'de> for Strict<RobinHoodHashSet<T, S>>
+        where
+            T: Deserialize<'de> + Hash + Eq,
+            S: BuildHasher + Default,
+        {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer
+                    .deserialize_seq(SetVisitor {
+                        strict: true,
+                        marker: PhantomData,
+                    })
+                    .map(Strict)
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub use serde_impls::Strict;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rh_hash_table::{
+        ConfigError, Entry, KeyValuePair, ProbeLimitAction, ProbeLimitEvent, RobinHoodHashSet,
+        RobinHoodHashTable, RobinHoodHashTableBuilder, SlotLayout, SplitKeyValue, TableStats,
+        TryReserveError,
+    };
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+    use std::num::NonZeroUsize;
+
+    /// Passes integer keys straight through, so tests control the home slots.
+    #[derive(Default)]
+    struct IdentityHasher(u64);
+
+    impl Hasher for IdentityHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for byte in bytes {
+                self.0 = (self.0 << 8) | u64::from(*byte);
+            }
+        }
+
+        fn write_u64(&mut self, i: u64) {
+            self.0 = i;
+        }
+    }
+
+    type IdentityState = BuildHasherDefault<IdentityHasher>;
+
+    /// Small xorshift generator so churn tests are reproducible.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    /// Applies `steps` random removes, `get_mut` updates and inserts on keys
+    /// below `keys` to both `rht` and `expected`, checking that every result
+    /// and membership test agrees.
+    fn churn<L, S, V>(
+        rht: &mut RobinHoodHashTable<L, S>,
+        expected: &mut std::collections::HashMap<u64, V>,
+        rng: &mut XorShift,
+        steps: usize,
+        keys: u64,
+        value: impl Fn(u64) -> V,
+        update: impl Fn(&mut V),
+    ) where
+        L: SlotLayout<Key = u64, Value = V>,
+        S: BuildHasher,
+        V: PartialEq + std::fmt::Debug,
+    {
+        for _ in 0..steps {
+            let key = rng.next() % keys;
+            match rng.next() % 4 {
+                0 => assert_eq!(rht.remove(&key), expected.remove(&key)),
+                1 => {
+                    if let Some(value) = rht.get_mut(&key) {
+                        update(value);
+                    }
+                    if let Some(value) = expected.get_mut(&key) {
+                        update(value);
+                    }
+                }
+                _ => assert_eq!(
+                    rht.insert(key, value(key)),
+                    expected.insert(key, value(key))
+                ),
+            }
+            assert_eq!(rht.contains(&key), expected.contains_key(&key));
+        }
+    }
+
+    #[test]
+    fn insert_test_for_all_cases() {
+        let mut rht = RobinHoodHashTable::with_capacity(3);
+        rht.insert(String::from("pineapple"), 1);
+        assert!(rht.contains("pineapple"));
+
+        rht.insert(String::from("carrot"), 2);
+        rht.insert(String::from("cucumber"), 3);
+
+        assert!(rht.contains("carrot"));
+        assert!(rht.contains("cucumber"));
+    }
+    #[test]
+    fn contains_test_for_search_key_that_exists() {
+        let mut rht = RobinHoodHashTable::with_capacity(3);
+        rht.insert("pine tree", 1);
+        assert!(rht.contains("pine tree"));
+    }
+
+    #[test]
+    fn contains_test_for_search_key_that_doesnt_exist() {
+        let rht = RobinHoodHashTable::<KeyValuePair<&str, i64>>::with_capacity(3);
+        assert!(!rht.contains("pine tree"));
+    }
+
+    #[test]
+    fn remove_key_from_table() {
+        let mut rht = RobinHoodHashTable::with_capacity(3);
+        rht.insert("pine tree", 1);
+        assert!(rht.contains("pine tree"));
+
+        assert_eq!(rht.remove("pine tree"), Some(1));
+
+        assert!(!rht.contains("pine tree"));
+
+        assert_eq!(rht.remove("pine tree"), None);
+    }
+
+    #[test]
+    fn get_returns_stored_value() {
+        let mut rht = RobinHoodHashTable::with_capacity(3);
+        rht.insert("pine tree", 1);
+        assert_eq!(rht.get(&"pine tree"), Some(&1));
+        assert_eq!(rht.get(&"oak tree"), None);
+        assert_eq!(rht.get_key_value(&"pine tree"), Some((&"pine tree", &1)));
+    }
+
+    #[test]
+    fn get_mut_updates_stored_value() {
+        let mut rht = RobinHoodHashTable::with_capacity(3);
+        rht.insert("pine tree", 1);
+        if let Some(value) = rht.get_mut(&"pine tree") {
+            *value += 41;
+        }
+        assert_eq!(rht.get(&"pine tree"), Some(&42));
+        assert_eq!(rht.get_mut(&"oak tree"), None);
+    }
+
+    #[test]
+    fn insert_replaces_value_of_existing_key() {
+        let mut rht = RobinHoodHashTable::with_capacity(3);
+        assert_eq!(rht.insert("pine tree", 1), None);
+        assert_eq!(rht.insert("pine tree", 2), Some(1));
+        assert_eq!(rht.get(&"pine tree"), Some(&2));
+    }
+
+    #[test]
+    fn insert_keeps_every_key_reachable() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        for i in 0..200 {
+            assert_eq!(rht.insert(i, i * 10), None);
+        }
+        for i in 0..200 {
+            assert_eq!(rht.insert(i, i * 20), Some(i * 10));
+        }
+        for i in 0..200 {
+            assert_eq!(rht.get(&i), Some(&(i * 20)));
+        }
+    }
+
+    #[test]
+    fn remove_entry_returns_key_and_value() {
+        let mut rht = RobinHoodHashTable::with_capacity(3);
+        rht.insert(String::from("pine tree"), 1);
+        assert_eq!(
+            rht.remove_entry("pine tree"),
+            Some((String::from("pine tree"), 1))
+        );
+        assert!(rht.is_empty());
+    }
+
+    #[test]
+    fn remove_shifts_cluster_back() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        for i in 0..200 {
+            rht.insert(i, i);
+        }
+        for i in (0..200).step_by(3) {
+            assert_eq!(rht.remove(&i), Some(i));
+        }
+        assert_eq!(rht.len(), 200 - 67);
+        for i in 0..200 {
+            let expected = if i % 3 == 0 { None } else { Some(&i) };
+            assert_eq!(rht.get(&i), expected);
+        }
+    }
+
+    #[test]
+    fn entry_counts_words() {
+        let mut rht = RobinHoodHashTable::with_capacity(2);
+        for word in "the cat and the dog and the bird".split(' ') {
+            *rht.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(rht.get(&"the"), Some(&3));
+        assert_eq!(rht.get(&"and"), Some(&2));
+        assert_eq!(rht.get(&"bird"), Some(&1));
+        assert_eq!(rht.len(), 5);
+    }
+
+    #[test]
+    fn entry_and_modify_or_default() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        rht.entry("pine tree").and_modify(|v| *v += 1).or_default();
+        assert_eq!(rht.get(&"pine tree"), Some(&0));
+        rht.entry("pine tree").and_modify(|v| *v += 1).or_default();
+        assert_eq!(rht.get(&"pine tree"), Some(&1));
+        assert_eq!(*rht.entry("oak tree").or_insert_with(|| 7), 7);
+    }
+
+    #[test]
+    fn occupied_entry_remove() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        for i in 0..50 {
+            rht.insert(i, i);
+        }
+        match rht.entry(10) {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 10),
+            Entry::Vacant(_) => panic!("key 10 should be present"),
+        }
+        assert!(!rht.contains(&10));
+        for i in (0..50).filter(|i| *i != 10) {
+            assert_eq!(rht.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn iter_visits_every_entry_once() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        for i in 0..100 {
+            rht.insert(i, i * 2);
+        }
+        let iter = rht.iter();
+        assert_eq!(iter.len(), 100);
+        let mut pairs: Vec<(i32, i32)> = iter.map(|(k, v)| (*k, *v)).collect();
+        pairs.sort();
+        assert_eq!(pairs, (0..100).map(|i| (i, i * 2)).collect::<Vec<_>>());
+
+        let mut keys: Vec<i32> = rht.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, (0..100).collect::<Vec<_>>());
+        assert_eq!(
+            rht.values().sum::<i32>(),
+            (0..100).map(|i| i * 2).sum::<i32>()
+        );
+    }
+
+    #[test]
+    fn iter_mut_and_values_mut_update_in_place() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        for i in 0..20 {
+            rht.insert(i, i);
+        }
+        for (_, value) in rht.iter_mut() {
+            *value += 1;
+        }
+        for value in rht.values_mut() {
+            *value *= 10;
+        }
+        for i in 0..20 {
+            assert_eq!(rht.get(&i), Some(&((i + 1) * 10)));
+        }
+    }
+
+    #[test]
+    fn into_iter_and_drain_yield_owned_pairs() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        for i in 0..20 {
+            rht.insert(i.to_string(), i);
+        }
+        let mut drain = rht.drain();
+        assert_eq!(drain.size_hint(), (20, Some(20)));
+        let first = drain.next().unwrap();
+        drop(drain);
+        assert!(rht.is_empty());
+        assert!(!rht.contains(&first.0));
+
+        rht.insert(String::from("pine tree"), 1);
+        let owned: Vec<(String, i32)> = rht.into_iter().collect();
+        assert_eq!(owned, vec![(String::from("pine tree"), 1)]);
+    }
+
+    #[test]
+    fn leaked_drain_leaves_the_rest_in_the_table() {
+        let mut rht = RobinHoodHashTable::with_capacity_and_hasher(16, SeededState(3));
+        rht.extend((0..13u64).map(|key| (key, key * 2)));
+        let mut drain = rht.drain();
+        let taken: Vec<u64> = drain.by_ref().take(5).map(|(key, _)| key).collect();
+        std::mem::forget(drain);
+
+        assert_eq!(rht.len(), 8);
+        rht.debug_assert_invariants();
+        for key in 0..13u64 {
+            let expected = Some(key * 2).filter(|_| !taken.contains(&key));
+            assert_eq!(rht.get(&key).copied(), expected);
+        }
+        rht.extend((100..110u64).map(|key| (key, key)));
+        assert_eq!(rht.len(), 18);
+        rht.debug_assert_invariants();
+    }
+
+    #[test]
+    fn with_hasher_uses_supplied_build_hasher() {
+        let mut rht = RobinHoodHashTable::with_capacity_and_hasher(8, IdentityState::default());
+        for i in 0..8u64 {
+            rht.insert(i, i);
+        }
+        assert_eq!(rht.len(), 8);
+        for i in 0..8u64 {
+            assert_eq!(rht.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn deterministic_hasher_gives_reproducible_layout() {
+        let state = BuildHasherDefault::<DefaultHasher>::default();
+        let mut first = RobinHoodHashTable::with_hasher(state.clone());
+        let mut second = RobinHoodHashTable::with_hasher(state);
+        for word in ["pine", "oak", "birch", "maple", "cedar"] {
+            first.insert(word, word.len());
+            second.insert(word, word.len());
+        }
+        let first_order: Vec<_> = first.keys().collect();
+        let second_order: Vec<_> = second.keys().collect();
+        assert_eq!(first_order, second_order);
+    }
+
+    #[test]
+    fn string_keys_can_be_queried_with_str() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        rht.insert(String::from("pine tree"), 1);
+        rht.insert(String::from("oak tree"), 2);
+        assert!(rht.contains("pine tree"));
+        assert_eq!(rht.get("oak tree"), Some(&2));
+        if let Some(value) = rht.get_mut("oak tree") {
+            *value = 3;
+        }
+        assert_eq!(rht.remove("oak tree"), Some(3));
+        assert_eq!(
+            rht.get_key_value("pine tree"),
+            Some((&String::from("pine tree"), &1))
+        );
+    }
+
+    #[test]
+    fn stores_keys_and_values_without_clone_or_display() {
+        struct Handle {
+            id: usize,
+        }
+
+        let mut rht = RobinHoodHashTable::with_capacity(2);
+        for i in 0..32u8 {
+            rht.insert(vec![i, i + 1], Handle { id: i as usize });
+        }
+        assert_eq!(rht.len(), 32);
+        assert_eq!(rht.get(&vec![5, 6]).map(|handle| handle.id), Some(5));
+        assert_eq!(rht.remove(&vec![7, 8]).map(|handle| handle.id), Some(7));
+        assert!(!rht.contains(&vec![7, 8]));
+    }
+
+    #[test]
+    fn capacity_is_rounded_to_power_of_two() {
+        let rht = RobinHoodHashTable::<KeyValuePair<u64, u64>>::with_capacity(3);
+        assert_eq!(rht.capacity(), 4);
+        let rht = RobinHoodHashTable::<KeyValuePair<u64, u64>>::with_capacity(0);
+        assert_eq!(rht.capacity(), 1);
+        let rht = RobinHoodHashTable::<KeyValuePair<u64, u64>, _>::with_capacity_and_hasher(
+            100,
+            IdentityState::default(),
+        );
+        assert_eq!(rht.capacity(), 128);
+    }
+
+    #[test]
+    fn weak_hasher_with_strided_keys_stays_reachable() {
+        let mut rht = RobinHoodHashTable::with_capacity_and_hasher(2, IdentityState::default());
+        for i in 0..1000u64 {
+            rht.insert(i << 10, i);
+        }
+        assert!(rht.capacity().is_power_of_two());
+        for i in 0..1000u64 {
+            assert_eq!(rht.get(&(i << 10)), Some(&i));
+        }
+    }
+
+    #[test]
+    fn resize_reuses_cached_hashes() {
+        use std::cell::Cell;
+
+        thread_local! {
+            static HASH_CALLS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        #[derive(Default)]
+        struct CountingHasher(DefaultHasher);
+
+        impl Hasher for CountingHasher {
+            fn finish(&self) -> u64 {
+                HASH_CALLS.with(|calls| calls.set(calls.get() + 1));
+                self.0.finish()
+            }
+
+            fn write(&mut self, bytes: &[u8]) {
+                self.0.write(bytes);
+            }
+        }
+
+        let mut rht = RobinHoodHashTable::with_capacity_and_hasher(
+            2,
+            BuildHasherDefault::<CountingHasher>::default(),
+        );
+        for i in 0..500 {
+            rht.insert(i, i);
+        }
+        assert!(rht.capacity() > 500);
+        assert_eq!(HASH_CALLS.with(Cell::get), 500);
+        for i in 0..500 {
+            assert_eq!(rht.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn displaced_entries_are_written_back() {
+        let mut rht = RobinHoodHashTable::with_capacity_and_hasher(64, IdentityState::default());
+        for i in 0..57u64 {
+            assert_eq!(rht.insert(i, i), None);
+            rht.debug_assert_invariants();
+        }
+        assert_eq!(rht.len(), 57);
+        for i in 0..57u64 {
+            assert_eq!(rht.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn invariants_hold_under_churn() {
+        let mut rng = XorShift(0x2545_F491_4F6C_DD1D);
+        let mut rht = RobinHoodHashTableBuilder::new()
+            .capacity(8)
+            .max_load_factor(0.95)
+            .build()
+            .unwrap();
+        let mut expected = std::collections::HashMap::new();
+        churn(
+            &mut rht,
+            &mut expected,
+            &mut rng,
+            20_000,
+            512,
+            |key| key * 2,
+            |value| *value += 1,
+        );
+        rht.debug_assert_invariants();
+        assert_eq!(rht.len(), expected.len());
+        for (key, value) in &expected {
+            assert_eq!(rht.get(key), Some(value));
+        }
+    }
+
+    #[test]
+    fn incremental_resize_migrates_a_bounded_number_of_slots() {
+        // The next growth comes before a one-slot batch could finish, so the
+        // table has to pick up the pace instead of flushing at that growth.
+        for batch in [1, 4] {
+            let mut rht = RobinHoodHashTable::with_capacity(16);
+            rht.set_incremental_resize(NonZeroUsize::new(batch));
+            for i in 0..5000 {
+                let (capacity, before) = (rht.capacity(), rht.migration_work_left());
+                rht.insert(i, i);
+                let left = if rht.capacity() == capacity {
+                    rht.migration_work_left()
+                } else {
+                    0
+                };
+                assert!(
+                    before - left <= batch + 3,
+                    "insert {} migrated {} slots",
+                    i,
+                    before - left
+                );
+            }
+        }
+
+        let mut rht = RobinHoodHashTable::with_capacity(16);
+        rht.set_incremental_resize(NonZeroUsize::new(4));
+        let mut saw_resize = false;
+        for i in 0..2000 {
+            rht.insert(i, i);
+            if rht.is_resizing() {
+                saw_resize = true;
+                rht.debug_assert_invariants();
+                assert_eq!(rht.get(&0), Some(&0));
+                assert_eq!(rht.get(&i), Some(&i));
+            }
+        }
+        assert!(saw_resize);
+        assert_eq!(rht.len(), 2000);
+        assert_eq!(rht.iter().count(), 2000);
+        for i in 0..2000 {
+            assert_eq!(rht.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn incremental_resize_handles_updates_and_removes_mid_migration() {
+        let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+        let mut rht = RobinHoodHashTable::with_capacity(8);
+        rht.set_incremental_resize(NonZeroUsize::new(1));
+        let mut expected = std::collections::HashMap::new();
+        churn(
+            &mut rht,
+            &mut expected,
+            &mut rng,
+            20_000,
+            1024,
+            |key| key,
+            |value| *value += 1,
+        );
+        rht.debug_assert_invariants();
+        assert_eq!(rht.len(), expected.len());
+        let mut pairs: Vec<_> = rht.into_iter().collect();
+        let mut expected: Vec<_> = expected.into_iter().collect();
+        pairs.sort();
+        expected.sort();
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn removing_below_min_load_factor_shrinks() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        rht.set_min_load_factor(0.25).unwrap();
+        for i in 0..1000 {
+            rht.insert(i, i);
+        }
+        let grown = rht.capacity();
+        for i in 0..990 {
+            rht.remove(&i);
+        }
+        assert!(rht.capacity() < grown);
+        assert!(rht.len() as f64 / rht.capacity() as f64 >= 0.25);
+        rht.debug_assert_invariants();
+        for i in 990..1000 {
+            assert_eq!(rht.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn reserve_and_shrink_to() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        rht.reserve(1000);
+        let reserved = rht.capacity();
+        assert!(reserved >= 1112);
+        for i in 0..1000 {
+            rht.insert(i, i);
+        }
+        assert_eq!(rht.capacity(), reserved);
+
+        for i in 10..1000 {
+            rht.remove(&i);
+        }
+        assert_eq!(rht.capacity(), reserved);
+        rht.shrink_to(usize::MAX);
+        rht.shrink_to(usize::MAX / 2 + 2);
+        rht.shrink_to(reserved + 1);
+        assert_eq!(rht.capacity(), reserved);
+        rht.shrink_to(64);
+        assert_eq!(rht.capacity(), 64);
+        rht.shrink_to_fit();
+        assert_eq!(rht.capacity(), 16);
+        rht.debug_assert_invariants();
+        for i in 0..10 {
+            assert_eq!(rht.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn clear_keeps_capacity() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        for i in 0..100 {
+            rht.insert(i, i);
+        }
+        let capacity = rht.capacity();
+        rht.clear();
+        assert!(rht.is_empty());
+        assert_eq!(rht.capacity(), capacity);
+        assert!(!rht.contains(&5));
+        rht.insert(5, 5);
+        assert_eq!(rht.get(&5), Some(&5));
+    }
+
+    #[test]
+    fn try_reserve_reports_capacity_overflow() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        rht.insert(1, 1);
+        assert_eq!(
+            rht.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert!(matches!(
+            rht.try_reserve(usize::MAX / 4),
+            Err(TryReserveError::AllocError(_))
+        ));
+        assert_eq!(rht.capacity(), 4);
+        assert_eq!(rht.get(&1), Some(&1));
+        assert_eq!(rht.try_reserve(100), Ok(()));
+        assert!(rht.capacity() >= 128);
+    }
+
+    #[test]
+    fn try_with_capacity_and_try_insert() {
+        assert_eq!(
+            RobinHoodHashTable::<KeyValuePair<u64, u64>>::try_with_capacity(usize::MAX).err(),
+            Some(TryReserveError::CapacityOverflow)
+        );
+        let mut rht = RobinHoodHashTable::try_with_capacity(2).unwrap();
+        for i in 0..100u64 {
+            assert_eq!(rht.try_insert(i, i), Ok(None));
+        }
+        assert_eq!(rht.try_insert(7, 70), Ok(Some(7)));
+        assert_eq!(rht.len(), 100);
+        rht.debug_assert_invariants();
+
+        // Both paths grow by the configured factor.
+        let build = || {
+            RobinHoodHashTableBuilder::new()
+                .capacity(2)
+                .growth_factor(4)
+                .build::<u64, u64>()
+                .unwrap()
+        };
+        let (mut inserted, mut tried) = (build(), build());
+        for i in 0..100u64 {
+            inserted.insert(i, i);
+            assert_eq!(tried.try_insert(i, i), Ok(None));
+            assert_eq!(tried.capacity(), inserted.capacity());
+        }
+        assert_eq!(tried.capacity(), 128);
+        assert_eq!(tried.stats().resizes, inserted.stats().resizes);
+    }
+
+    #[test]
+    fn builder_rejects_invalid_options() {
+        let build = |builder: RobinHoodHashTableBuilder| builder.build::<u64, u64>();
+        assert_eq!(
+            build(RobinHoodHashTableBuilder::new().max_load_factor(0.0)).err(),
+            Some(ConfigError::MaxLoadFactor(0.0))
+        );
+        assert_eq!(
+            build(RobinHoodHashTableBuilder::new().max_load_factor(1.5)).err(),
+            Some(ConfigError::MaxLoadFactor(1.5))
+        );
+        assert!(matches!(
+            build(RobinHoodHashTableBuilder::new().max_load_factor(f64::NAN)),
+            Err(ConfigError::MaxLoadFactor(_))
+        ));
+        assert_eq!(
+            build(RobinHoodHashTableBuilder::new().growth_factor(3)).err(),
+            Some(ConfigError::GrowthFactor(3))
+        );
+        assert_eq!(
+            build(RobinHoodHashTableBuilder::new().min_load_factor(0.5)).err(),
+            Some(ConfigError::MinLoadFactor(0.5))
+        );
+        assert_eq!(
+            build(RobinHoodHashTableBuilder::new().max_probe_length(0)).err(),
+            Some(ConfigError::MaxProbeLength(0))
+        );
+        assert_eq!(
+            build(RobinHoodHashTableBuilder::new().capacity(usize::MAX)).err(),
+            Some(ConfigError::Allocation(TryReserveError::CapacityOverflow))
+        );
+    }
+
+    #[test]
+    fn builder_applies_options() {
+        let mut rht = RobinHoodHashTableBuilder::new()
+            .capacity(0)
+            .max_load_factor(0.5)
+            .growth_factor(4)
+            .hasher(IdentityState::default())
+            .build()
+            .unwrap();
+        assert_eq!(rht.capacity(), 1);
+        for i in 0..100u64 {
+            rht.insert(i, i);
+        }
+        assert_eq!(rht.capacity(), 256);
+        rht.debug_assert_invariants();
+    }
+
+    #[test]
+    fn new_and_default_create_empty_tables() {
+        let rht: RobinHoodHashTable<KeyValuePair<String, u32>> = RobinHoodHashTable::new();
+        assert!(rht.is_empty());
+        let rht: RobinHoodHashTable<KeyValuePair<u64, u64>, IdentityState> = Default::default();
+        assert_eq!(rht.capacity(), 16);
+    }
+
+    #[test]
+    fn max_probe_length_forces_growth() {
+        let mut rht = RobinHoodHashTableBuilder::new()
+            .capacity(1024)
+            .max_load_factor(1.0)
+            .max_probe_length(1)
+            .build()
+            .unwrap();
+        for i in 0..700 {
+            rht.insert(i, i);
+        }
+        assert!(rht.capacity() > 1024);
+        rht.debug_assert_invariants();
+        for i in 0..700 {
+            assert_eq!(rht.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn try_insert_reports_a_failed_probe_limit_resize() {
+        let mut rht = RobinHoodHashTableBuilder::new()
+            .capacity(1024)
+            .hasher(SeededState(0))
+            .growth_factor(1 << (usize::BITS - 2))
+            .max_probe_length(2)
+            .build()
+            .unwrap();
+        for key in 0..4u64 {
+            assert_eq!(rht.try_insert(key, key), Ok(None));
+        }
+        // The fourth colliding key broke the limit; growing by the configured
+        // factor overflows, and the reaction stays pending.
+        assert_eq!(rht.probe_limit_hits(), 1);
+        for _ in 0..2 {
+            assert_eq!(rht.try_insert(4, 4), Err(TryReserveError::CapacityOverflow));
+        }
+        assert_eq!(rht.probe_limit_hits(), 1);
+        assert_eq!(rht.len(), 4);
+        assert_eq!(rht.capacity(), 1024);
+        assert_eq!(rht.get(&3), Some(&3));
+        rht.debug_assert_invariants();
+    }
+
+    /// Hashes every key to the same value unless reseeded with a nonzero seed,
+    /// standing in for a key set chosen against a known seed.
+    #[derive(Clone, Default)]
+    struct SeededState(u64);
+
+    struct SeededHasher(u64, u64);
+
+    impl Hasher for SeededHasher {
+        fn finish(&self) -> u64 {
+            if self.0 == 0 {
+                0
+            } else {
+                self.1.wrapping_mul(self.0)
+            }
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for byte in bytes {
+                self.1 = (self.1 << 8 | u64::from(*byte)).wrapping_mul(0x100_0000_01b3);
+            }
+        }
+    }
+
+    impl std::hash::BuildHasher for SeededState {
+        type Hasher = SeededHasher;
+
+        fn build_hasher(&self) -> SeededHasher {
+            SeededHasher(self.0, 0)
+        }
+    }
+
+    #[test]
+    fn probe_limit_reseeds_the_hasher() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let reseeds = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&reseeds);
+        let mut rht = RobinHoodHashTableBuilder::new()
+            .capacity(256)
+            .hasher(SeededState(0))
+            .reseed_with(|| SeededState(0x9E37_79B9_7F4A_7C15))
+            .on_probe_limit(move |event: &ProbeLimitEvent| {
+                if event.action == ProbeLimitAction::Reseed {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .max_probe_length(8)
+            .build()
+            .unwrap();
+        for i in 0..100u64 {
+            rht.insert(i, i);
+        }
+        assert_eq!(reseeds.load(Ordering::SeqCst), 1);
+        assert!(rht.probe_limit_hits() >= 1);
+        assert_eq!(rht.hasher().0, 0x9E37_79B9_7F4A_7C15);
+        assert_eq!(rht.capacity(), 256);
+        rht.debug_assert_invariants();
+        for i in 0..100u64 {
+            assert_eq!(rht.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn probe_limit_is_reported_by_the_insert_that_breaks_it() {
+        use std::sync::{Arc, Mutex};
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&events);
+        let mut rht = RobinHoodHashTableBuilder::new()
+            .capacity(8)
+            .hasher(SeededState(0))
+            .max_probe_length(2)
+            .on_probe_limit(move |event: &ProbeLimitEvent| log.lock().unwrap().push(event.clone()))
+            .build()
+            .unwrap();
+        for key in 0..4u64 {
+            rht.insert(key, key);
+        }
+        assert_eq!(rht.probe_limit_hits(), 1);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [ProbeLimitEvent {
+                probe_length: 3,
+                len: 4,
+                capacity: 8,
+                action: ProbeLimitAction::Grow,
+            }]
+        );
+        // Only the growth waits for the next insert, and emptying the table
+        // drops it.
+        assert_eq!(rht.capacity(), 8);
+        rht.clear();
+        rht.insert(0, 0);
+        assert_eq!(rht.capacity(), 8);
+        assert_eq!(events.lock().unwrap().len(), 1);
+
+        rht.extend((1..4u64).map(|key| (key, key)));
+        rht.drain().for_each(drop);
+        rht.insert(0, 0);
+        assert_eq!(rht.capacity(), 8);
+        assert_eq!(rht.probe_limit_hits(), 2);
+    }
+
+    #[test]
+    fn probe_limit_growth_is_bounded_for_colliding_keys() {
+        let mut rht = RobinHoodHashTableBuilder::new()
+            .capacity(16)
+            .hasher(SeededState(0))
+            .max_probe_length(4)
+            .build()
+            .unwrap();
+        for i in 0..200u64 {
+            rht.insert(i, i);
+        }
+        assert!(rht.probe_limit_hits() > 100);
+        assert!(rht.capacity() <= 1024);
+        for i in 0..200u64 {
+            assert_eq!(rht.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn stats_of_a_hand_placed_table() {
+        let mut rht = RobinHoodHashTable::with_capacity_and_hasher(8, SeededState(0));
+        assert_eq!(
+            rht.stats(),
+            TableStats {
+                len: 0,
+                capacity: 8,
+                load_factor: 0.0,
+                mean_probe_length: 0.0,
+                probe_length_variance: 0.0,
+                max_probe_length: 0,
+                probe_length_histogram: vec![],
+                cluster_length_histogram: vec![0],
+                resizes: 0,
+                allocated_bytes: 8 * 14,
+            }
+        );
+
+        // Every key hashes to zero, so all three share a home slot.
+        for key in 0..3u64 {
+            rht.insert(key, ());
+        }
+        let stats = rht.stats();
+        assert_eq!(stats.len, 3);
+        assert_eq!(stats.load_factor, 3.0 / 8.0);
+        assert_eq!(stats.probe_length_histogram, vec![1, 1, 1]);
+        assert_eq!(stats.max_probe_length, 2);
+        assert_eq!(stats.mean_probe_length, 1.0);
+        assert!((stats.probe_length_variance - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(stats.cluster_length_histogram, vec![0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn stats_track_resizes_and_agree_with_len() {
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        let mut rng = XorShift(0x5eed);
+        for _ in 0..1000 {
+            rht.insert(rng.next(), ());
+        }
+        let stats = rht.stats();
+        assert_eq!(stats.resizes, 9);
+        assert_eq!(stats.capacity, 2048);
+        assert_eq!(
+            stats.probe_length_histogram.iter().sum::<usize>(),
+            rht.len()
+        );
+        let clustered: usize = stats
+            .cluster_length_histogram
+            .iter()
+            .enumerate()
+            .map(|(length, count)| length * count)
+            .sum();
+        assert_eq!(clustered, rht.len());
+        assert!(stats.load_factor < 0.9);
+    }
+
+    #[test]
+    fn slot_metadata_is_compact() {
+        // One u16 of metadata, a cached u32 hash and the bare pair: 14 bytes a
+        // slot, down from 32 when each slot was an `Option` holding an i64 PSL.
+        let rht = RobinHoodHashTable::<KeyValuePair<u32, u32>>::with_capacity(1024);
+        assert_eq!(rht.stats().allocated_bytes, 1024 * 14);
+    }
+
+    #[test]
+    fn every_stored_value_is_dropped_once() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut rht = RobinHoodHashTable::with_capacity(4);
+        rht.set_incremental_resize(NonZeroUsize::new(2));
+        for i in 0..120u64 {
+            rht.insert(i, Rc::clone(&value));
+        }
+        assert!(rht.is_resizing());
+        let copy = rht.clone();
+        assert_eq!(Rc::strong_count(&value), 241);
+
+        rht.insert(0, Rc::clone(&value));
+        for i in 0..10u64 {
+            rht.remove(&i);
+        }
+        assert_eq!(Rc::strong_count(&value), 231);
+        rht.drain().take(5).for_each(drop);
+        assert!(rht.is_empty());
+        assert_eq!(Rc::strong_count(&value), 121);
+
+        let mut into_iter = copy.into_iter();
+        into_iter.next();
+        drop(into_iter);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn split_layout_matches_std_hashmap_mid_migration() {
+        let mut rng = XorShift(0x0DDB_1A5E_5BAD_5EED);
+        let mut rht: RobinHoodHashTable<SplitKeyValue<u64, String>> =
+            RobinHoodHashTableBuilder::new()
+                .capacity(8)
+                .incremental_resize(NonZeroUsize::new(2).unwrap())
+                .build_with_layout()
+                .unwrap();
+        let mut expected = std::collections::HashMap::new();
+        churn(
+            &mut rht,
+            &mut expected,
+            &mut rng,
+            20_000,
+            1024,
+            |key| key.to_string(),
+            |value| value.push('!'),
+        );
+        rht.debug_assert_invariants();
+        for (_, value) in rht.iter_mut() {
+            value.push('?');
+        }
+        for value in expected.values_mut() {
+            value.push('?');
+        }
+        let copy = rht.clone();
+        let mut pairs: Vec<_> = rht.into_iter().collect();
+        let mut expected: Vec<_> = expected.into_iter().collect();
+        pairs.sort();
+        expected.sort();
+        assert_eq!(pairs, expected);
+        assert_eq!(copy.len(), expected.len());
+    }
+
+    #[test]
+    fn split_layout_keeps_keys_and_values_apart() {
+        let mut rht: RobinHoodHashTable<SplitKeyValue<u32, u32>> = Default::default();
+        for i in 0..10 {
+            rht.entry(i).or_insert(i * 3);
+        }
+        assert_eq!(rht.get(&4), Some(&12));
+        assert_eq!(rht.values().sum::<u32>(), 135);
+        assert_eq!(rht.stats().allocated_bytes, rht.capacity() * 14);
+    }
+
+    /// `contains` and `get` go through the SIMD scan when the `simd` feature
+    /// is on, while `entry` always probes one slot at a time, so comparing the
+    /// two checks that both paths agree. The 8- and 16-slot groups are also
+    /// checked on their own, the latter where AVX2 is available.
+    fn assert_lookup_paths_agree<S: std::hash::BuildHasher>(
+        rht: &mut RobinHoodHashTable<KeyValuePair<u64, u64>, S>,
+        keys: impl Iterator<Item = u64>,
+    ) {
+        for key in keys {
+            let found = rht.get(&key).copied();
+            assert_eq!(rht.contains(&key), found.is_some());
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            {
+                assert_eq!(rht.get_with_group_width(&key, 8).copied(), found);
+                if is_x86_feature_detected!("avx2") {
+                    assert_eq!(rht.get_with_group_width(&key, 16).copied(), found);
+                }
+            }
+            match rht.entry(key) {
+                Entry::Occupied(entry) => assert_eq!(Some(*entry.get()), found),
+                Entry::Vacant(..) => assert_eq!(None, found),
+            }
+        }
+    }
+
+    #[test]
+    fn lookup_paths_agree_on_random_keys() {
+        let mut rng = XorShift(0xA5A5_5A5A_DEAD_BEEF);
+        let mut rht = RobinHoodHashTable::with_capacity(64);
+        for _ in 0..5000 {
+            let key = rng.next() % 8192;
+            rht.insert(key, key);
+            if rng.next().is_multiple_of(4) {
+                rht.remove(&(rng.next() % 8192));
+            }
+        }
+        assert_lookup_paths_agree(&mut rht, 0..8192);
+    }
+
+    #[test]
+    fn lookup_paths_agree_on_long_colliding_runs() {
+        // Every key shares one home slot, so probes run across many groups
+        // and wrap past the end of the array.
+        let mut rht = RobinHoodHashTable::with_capacity_and_hasher(1024, SeededState(0));
+        for key in 0..300u64 {
+            rht.insert(key, key + 1);
+        }
+        for key in (0..300u64).step_by(7) {
+            rht.remove(&key);
+        }
+        rht.debug_assert_invariants();
+        assert_lookup_paths_agree(&mut rht, 0..400);
+    }
+
+    #[test]
+    fn lookup_paths_agree_on_a_nearly_full_table() {
+        let mut rht = RobinHoodHashTableBuilder::new()
+            .capacity(32)
+            .max_load_factor(1.0)
+            .hasher(IdentityState::default())
+            .build()
+            .unwrap();
+        for key in 0..31u64 {
+            rht.insert(key << 27, key);
+        }
+        assert_eq!(rht.capacity(), 32);
+        assert_lookup_paths_agree(&mut rht, (0..64u64).map(|key| key << 27));
+    }
+
+    /// Compares on `id` only, so tests can tell which of two equal values
+    /// the set kept.
+    #[derive(Debug)]
+    struct Tagged {
+        id: u32,
+        tag: &'static str,
+    }
+
+    impl PartialEq for Tagged {
+        fn eq(&self, other: &Self) -> bool {
+            self.id == other.id
+        }
+    }
+
+    impl Eq for Tagged {}
+
+    impl std::hash::Hash for Tagged {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.id.hash(state);
+        }
+    }
+
+    #[test]
+    fn set_insert_replace_and_take() {
+        let mut set = RobinHoodHashSet::new();
+        assert!(set.insert(Tagged {
+            id: 1,
+            tag: "first"
+        }));
+        assert!(!set.insert(Tagged {
+            id: 1,
+            tag: "second"
+        }));
+        assert_eq!(set.get(&Tagged { id: 1, tag: "" }).unwrap().tag, "first");
+
+        let old = set
+            .replace(Tagged {
+                id: 1,
+                tag: "third",
+            })
+            .unwrap();
+        assert_eq!(old.tag, "first");
+        assert_eq!(set.get(&Tagged { id: 1, tag: "" }).unwrap().tag, "third");
+        assert!(set.replace(Tagged { id: 2, tag: "new" }).is_none());
+        assert_eq!(set.len(), 2);
+
+        assert_eq!(set.take(&Tagged { id: 1, tag: "" }).unwrap().tag, "third");
+        assert!(set.take(&Tagged { id: 1, tag: "" }).is_none());
+        assert!(set.remove(&Tagged { id: 2, tag: "" }));
+        assert!(!set.remove(&Tagged { id: 2, tag: "" }));
+        assert!(set.is_empty());
+
+        let mut words: RobinHoodHashSet<String> = ["a", "b", "c"]
+            .iter()
+            .map(|word| word.to_string())
+            .collect();
+        assert!(words.contains("b"));
+        assert!(!words.contains("d"));
+        let mut drained: Vec<String> = words.drain().collect();
+        drained.sort();
+        assert_eq!(drained, ["a", "b", "c"]);
+        assert!(words.is_empty());
+    }
+
+    #[test]
+    fn set_algebra_matches_std_hashset() {
+        use std::collections::HashSet;
+
+        let a: RobinHoodHashSet<u32> = (0..200).filter(|n| n % 2 == 0).collect();
+        let b: RobinHoodHashSet<u32> = (0..300).filter(|n| n % 3 == 0).collect();
+        let std_a: HashSet<u32> = a.iter().copied().collect();
+        let std_b: HashSet<u32> = b.iter().copied().collect();
+
+        fn sorted<'a>(iter: impl Iterator<Item = &'a u32>) -> Vec<u32> {
+            let mut values: Vec<u32> = iter.copied().collect();
+            values.sort_unstable();
+            values
+        }
+
+        assert_eq!(sorted(a.union(&b)), sorted(std_a.union(&std_b)));
+        assert_eq!(
+            sorted(a.intersection(&b)),
+            sorted(std_a.intersection(&std_b))
+        );
+        assert_eq!(sorted(a.difference(&b)), sorted(std_a.difference(&std_b)));
+        assert_eq!(sorted(b.difference(&a)), sorted(std_b.difference(&std_a)));
+        assert_eq!(
+            sorted(a.symmetric_difference(&b)),
+            sorted(std_a.symmetric_difference(&std_b))
+        );
+
+        assert_eq!(sorted((&a | &b).iter()), sorted(a.union(&b)));
+        assert_eq!(sorted((&a & &b).iter()), sorted(a.intersection(&b)));
+        assert_eq!(sorted((&a - &b).iter()), sorted(a.difference(&b)));
+        assert_eq!(sorted((&a ^ &b).iter()), sorted(a.symmetric_difference(&b)));
+
+        let both = &a & &b;
+        assert!(both.is_subset(&a) && both.is_subset(&b));
+        assert!(a.is_superset(&both));
+        assert!(!a.is_subset(&b));
+        assert!(!a.is_disjoint(&b));
+        assert!((&a - &b).is_disjoint(&b));
+        assert!(RobinHoodHashSet::<u32>::new().is_subset(&a));
+    }
+
+    #[test]
+    fn standard_traits() {
+        let rht = RobinHoodHashTable::from([(1u32, "one"), (2, "two"), (3, "three")]);
+        assert_eq!(rht[&2], "two");
+        assert_eq!(rht.len(), 3);
+
+        // Equality ignores capacity and placement.
+        let mut other: RobinHoodHashTable<KeyValuePair<u32, &str>> =
+            RobinHoodHashTable::with_capacity(256);
+        other.extend(vec![(3, "three"), (1, "one")]);
+        assert_ne!(rht, other);
+        other.extend([(&2, &"two")].iter().copied());
+        assert_eq!(rht, other);
+        other.insert(2, "deux");
+        assert_ne!(rht, other);
+
+        let split: RobinHoodHashTable<SplitKeyValue<u32, &str>> = rht.clone().into_iter().collect();
+        assert_eq!(split[&3], "three");
+
+        let single: RobinHoodHashTable<KeyValuePair<&str, u32>> = [("k", 7)].into();
+        assert_eq!(format!("{:?}", single), r#"{"k": 7}"#);
+        let empty: RobinHoodHashTable<KeyValuePair<u32, u32>> = Default::default();
+        assert_eq!(format!("{:?}", empty), "{}");
+
+        let set = RobinHoodHashSet::from([1u8]);
+        assert_eq!(format!("{:?}", set), "{1}");
+        assert_eq!(set, [1u8].iter().copied().collect());
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_panics_on_a_missing_key() {
+        let rht = RobinHoodHashTable::from([("present", 1)]);
+        let _ = rht["missing"];
+    }
+
+    #[test]
+    fn retain_matches_std_hashmap() {
+        let mut rng = XorShift(0x1234_5678_9ABC_DEF0);
+        let mut rht = RobinHoodHashTable::with_capacity(16);
+        rht.set_incremental_resize(NonZeroUsize::new(3));
+        let mut map = std::collections::HashMap::new();
+        for round in 0..20u64 {
+            churn(
+                &mut rht,
+                &mut map,
+                &mut rng,
+                400,
+                4096,
+                |_| round,
+                |value| *value += 1,
+            );
+            let divisor = 2 + round % 5;
+            let keep = |key: &u64, value: &mut u64| {
+                *value += 1;
+                !key.is_multiple_of(divisor)
+            };
+            rht.retain(keep);
+            map.retain(keep);
+            rht.debug_assert_invariants();
+            assert_eq!(rht.len(), map.len());
+            for (key, value) in &map {
+                assert_eq!(rht.get(key), Some(value));
+            }
+        }
+    }
+
+    #[test]
+    fn extract_if_compacts_a_long_colliding_run() {
+        // Every key shares one home slot, so removals leave holes all along a
+        // run that fills nearly the whole table.
+        let mut rht = RobinHoodHashTableBuilder::new()
+            .capacity(64)
+            .max_load_factor(1.0)
+            .hasher(SeededState(0))
+            .build()
+            .unwrap();
+        for key in 0..63u64 {
+            rht.insert(key, key);
+        }
+        assert_eq!(rht.capacity(), 64);
+
+        let mut extracted: Vec<u64> = rht
+            .extract_if(|key, _| key % 3 == 0)
+            .map(|(key, _)| key)
+            .collect();
+        extracted.sort_unstable();
+        assert_eq!(extracted, (0..63).step_by(3).collect::<Vec<_>>());
+        rht.debug_assert_invariants();
+        for key in 0..63u64 {
+            assert_eq!(rht.contains(&key), key % 3 != 0);
+        }
+    }
+
+    #[test]
+    fn leaked_extract_if_keeps_the_rest_reachable() {
+        // One long run, so each extraction leaves the entries after it
+        // displaced until they are shifted back.
+        let mut rht = RobinHoodHashTableBuilder::new()
+            .capacity(64)
+            .hasher(SeededState(0))
+            .build()
+            .unwrap();
+        rht.extend((0..40u64).map(|key| (key, key)));
+        let mut extract = rht.extract_if(|key, _| key % 4 == 1);
+        let taken: Vec<u64> = extract.by_ref().take(3).map(|(key, _)| key).collect();
+        std::mem::forget(extract);
+
+        assert_eq!(taken.len(), 3);
+        assert_eq!(rht.len(), 37);
+        rht.debug_assert_invariants();
+        for key in 0..40u64 {
+            assert_eq!(rht.contains(&key), !taken.contains(&key));
+        }
+    }
+
+    #[test]
+    fn extract_if_keeps_what_it_did_not_reach() {
+        let mut rht: RobinHoodHashTable<KeyValuePair<u32, u32>> =
+            (0..1000).map(|key| (key, key)).collect();
+        let taken: Vec<(u32, u32)> = rht.extract_if(|key, _| key % 2 == 0).take(10).collect();
+        assert_eq!(taken.len(), 10);
+        rht.debug_assert_invariants();
+        assert_eq!(rht.len(), 990);
+        for (key, _) in taken {
+            assert!(!rht.contains(&key));
+        }
+        assert_eq!(rht.iter().filter(|(key, _)| *key % 2 == 0).count(), 490);
+    }
+
+    #[test]
+    fn extract_if_survives_a_panicking_predicate() {
+        let mut rht = RobinHoodHashTable::with_capacity_and_hasher(256, SeededState(0));
+        for key in 0..100u64 {
+            rht.insert(key, key);
+        }
+        let mut calls = 0;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            rht.retain(|_, _| {
+                calls += 1;
+                assert!(calls < 50, "predicate gave up");
+                calls % 2 == 0
+            })
+        }));
+        // Calls 1, 3, ..., 49 removed an entry before the 50th panicked.
+        assert!(result.is_err());
+        rht.debug_assert_invariants();
+        assert_eq!(rht.len(), 100 - 25);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trips_tables_and_sets() {
+        use crate::rh_hash_table::Strict;
+
+        let rht: RobinHoodHashTable<KeyValuePair<String, u32>> =
+            (0..100).map(|n| (n.to_string(), n)).collect();
+        let json = serde_json::to_string(&rht).unwrap();
+        let back: RobinHoodHashTable<KeyValuePair<String, u32>> =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(back, rht);
+        let split: RobinHoodHashTable<SplitKeyValue<String, u32>> =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(split["42"], 42);
+
+        let set: RobinHoodHashSet<u64> = (0..50).map(|n| n * 7).collect();
+        let json = serde_json::to_string(&set).unwrap();
+        let back: RobinHoodHashSet<u64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, set);
+        let Strict(back) = serde_json::from_str::<Strict<RobinHoodHashSet<u64>>>(&json).unwrap();
+        assert_eq!(back, set);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_strict_mode_rejects_duplicates() {
+        use crate::rh_hash_table::Strict;
+
+        let json = r#"{"a": 1, "b": 2, "a": 3}"#;
+        let lenient: RobinHoodHashTable<KeyValuePair<String, u32>> =
+            serde_json::from_str(json).unwrap();
+        assert_eq!(lenient.len(), 2);
+        assert_eq!(lenient["a"], 3);
+        let err =
+            serde_json::from_str::<Strict<RobinHoodHashTable<KeyValuePair<String, u32>>>>(json)
+                .unwrap_err();
+        assert!(err.to_string().contains("duplicate key"));
+
+        let lenient: RobinHoodHashSet<u8> = serde_json::from_str("[1, 2, 1]").unwrap();
+        assert_eq!(lenient.len(), 2);
+        assert!(serde_json::from_str::<Strict<RobinHoodHashSet<u8>>>("[1, 2, 1]").is_err());
+    }
+
+    impl crate::rh_hash_table::SnapshotHasher for SeededState {
+        fn seed(&self) -> u64 {
+            self.0
+        }
+
+        fn from_seed(seed: u64) -> Self {
+            SeededState(seed)
+        }
+    }
+
+    #[test]
+    fn snapshot_round_trips_without_rehashing() {
+        let mut rht = RobinHoodHashTable::with_capacity_and_hasher(16, SeededState(0x9E37));
+        rht.set_incremental_resize(NonZeroUsize::new(1));
+        for key in 0..500u64 {
+            rht.insert(format!("key {}", key), vec![key; (key % 4) as usize]);
+        }
+        assert!(rht.is_resizing());
+
+        let mut bytes = Vec::new();
+        rht.write_snapshot(&mut bytes).unwrap();
+        let loaded: RobinHoodHashTable<KeyValuePair<String, Vec<u64>>, SeededState> =
+            RobinHoodHashTable::read_snapshot(&bytes[..]).unwrap();
+        loaded.debug_assert_invariants();
+        assert!(!loaded.is_resizing());
+        assert_eq!(loaded.capacity(), rht.capacity());
+        assert_eq!(loaded.hasher().0, 0x9E37);
+        assert_eq!(loaded, rht);
+
+        // Slots are stored by position, so any layout can load them.
+        let split: RobinHoodHashTable<SplitKeyValue<String, Vec<u64>>, SeededState> =
+            RobinHoodHashTable::read_snapshot(&bytes[..]).unwrap();
+        assert_eq!(split["key 7"], [7, 7, 7]);
+        assert_eq!(split.len(), 500);
+
+        let empty: RobinHoodHashTable<KeyValuePair<u8, ()>, IdentityState> =
+            RobinHoodHashTable::with_hasher(IdentityState::default());
+        let mut bytes = Vec::new();
+        empty.write_snapshot(&mut bytes).unwrap();
+        let loaded: RobinHoodHashTable<KeyValuePair<u8, ()>, IdentityState> =
+            RobinHoodHashTable::read_snapshot(&bytes[..]).unwrap();
+        assert!(loaded.is_empty());
+    }
+
+    /// Builds a snapshot of `len` entries of a `SeededState(0)` table, in
+    /// which every key hashes to slot 0 and maps to itself.
+    fn colliding_snapshot(capacity: u64, len: u64, max_load: f64) -> Vec<u8> {
+        fn fnv(bytes: &[u8]) -> u64 {
+            bytes.iter().fold(0xcbf2_9ce4_8422_2325, |state, byte| {
+                (state ^ u64::from(*byte)).wrapping_mul(0x100_0000_01b3)
+            })
+        }
+        let mut bytes = b"RHHT".to_vec();
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        for field in [capacity, len, max_load.to_bits(), 0] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        let header = fnv(&bytes);
+        bytes.extend_from_slice(&header.to_le_bytes());
+        for index in 0..capacity {
+            if index < len {
+                let meta = index.min(u64::from(u16::MAX) - 1) as u16 + 1;
+                bytes.extend_from_slice(&meta.to_le_bytes());
+                bytes.extend_from_slice(&0u32.to_le_bytes());
+                bytes.extend_from_slice(&index.to_le_bytes());
+                bytes.extend_from_slice(&index.to_le_bytes());
+            } else {
+                bytes.extend_from_slice(&0u16.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        let checksum = fnv(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn snapshot_reads_only_its_own_bytes() {
+        use std::io::{Cursor, Read};
+
+        let rht: RobinHoodHashTable<KeyValuePair<u64, u64>, SeededState> =
+            (0..50).map(|key| (key, key)).collect();
+        let mut bytes = Vec::new();
+        rht.write_snapshot(&mut bytes).unwrap();
+        bytes.extend_from_slice(b"TRAILER");
+        let mut input = Cursor::new(bytes);
+        let loaded: RobinHoodHashTable<KeyValuePair<u64, u64>, SeededState> =
+            RobinHoodHashTable::read_snapshot(&mut input).unwrap();
+        assert_eq!(loaded, rht);
+        let mut rest = Vec::new();
+        input.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"TRAILER");
+    }
+
+    #[test]
+    fn snapshot_rejects_a_table_at_its_max_load() {
+        use crate::rh_hash_table::SnapshotError;
+
+        type Table = RobinHoodHashTable<KeyValuePair<u64, u64>, SeededState>;
+        let loaded = Table::read_snapshot(&colliding_snapshot(4, 3, 1.0)[..]).unwrap();
+        assert_eq!(loaded.len(), 3);
+        for (len, max_load) in [(4, 1.0), (3, 0.75), (5, 1.0)] {
+            assert!(matches!(
+                Table::read_snapshot(&colliding_snapshot(4, len, max_load)[..]),
+                Err(SnapshotError::Corrupt(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn snapshot_rejects_damaged_input() {
+        use crate::rh_hash_table::SnapshotError;
+
+        type Table = RobinHoodHashTable<KeyValuePair<u32, u32>, SeededState>;
+        let mut rht = Table::with_hasher(SeededState(7));
+        rht.extend((0..100).map(|key| (key, key * 3)));
+        let mut bytes = Vec::new();
+        rht.write_snapshot(&mut bytes).unwrap();
+        assert_eq!(&bytes[..4], b"RHHT");
+        // Magic, version, slot count, len, load factor and seed.
+        let checksum_at = 4 + 2 + 8 * 4;
+        let body_at = checksum_at + 8;
+
+        let mut bad = bytes.clone();
+        bad[0] = b'X';
+        assert!(matches!(
+            Table::read_snapshot(&bad[..]),
+            Err(SnapshotError::BadMagic)
+        ));
+
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        assert!(matches!(
+            Table::read_snapshot(&bad[..]),
+            Err(SnapshotError::UnsupportedVersion(2))
+        ));
+
+        // The header is checked before its slot count is allocated.
+        for at in [checksum_at, checksum_at - 8, checksum_at - 16, 6, 13] {
+            let mut bad = bytes.clone();
+            bad[at] ^= 1;
+            assert!(matches!(
+                Table::read_snapshot(&bad[..]),
+                Err(SnapshotError::ChecksumMismatch)
+            ));
+        }
+
+        // Flipping a value byte keeps the layout readable but not the
+        // checksum. The last 16 bytes are the pending count and the trailer.
+        let mut bad = bytes.clone();
+        let last = bad.len() - 17;
+        bad[last] ^= 0x40;
+        assert!(matches!(
+            Table::read_snapshot(&bad[..]),
+            Err(SnapshotError::ChecksumMismatch)
+        ));
+
+        let mut bad = bytes.clone();
+        let trailer = bad.len() - 1;
+        bad[trailer] ^= 1;
+        assert!(matches!(
+            Table::read_snapshot(&bad[..]),
+            Err(SnapshotError::ChecksumMismatch)
+        ));
+
+        assert!(matches!(
+            Table::read_snapshot(&bytes[..body_at + 10]),
+            Err(SnapshotError::Io(..))
+        ));
+
+        // A different hasher with the same seed puts keys in other slots.
+        let mut zero = Table::with_hasher(SeededState(0));
+        zero.extend((0..100).map(|key| (key, key)));
+        let mut zero_bytes = Vec::new();
+        zero.write_snapshot(&mut zero_bytes).unwrap();
+        assert!(matches!(
+            RobinHoodHashTable::<KeyValuePair<u32, u32>, IdentityState>::read_snapshot(
+                &zero_bytes[..]
+            ),
+            Err(SnapshotError::HasherMismatch)
+        ));
+
+        assert_eq!(Table::read_snapshot(&bytes[..]).unwrap(), rht);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn keyed_state_is_siphash_and_snapshots() {
+        use crate::rh_hash_table::KeyedState;
+        use std::hash::SipHasher;
+
+        let state = KeyedState::with_seed(0x0123_4567_89AB_CDEF);
+        let k1 = state.seed().wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        for len in 0..40 {
+            let bytes: Vec<u8> = (0..len as u8).collect();
+            let mut ours = state.build_hasher();
+            let mut std = SipHasher::new_with_keys(state.seed(), k1);
+            // Split writes must hash the same as one write.
+            let (head, tail) = bytes.split_at(len / 3);
+            ours.write(head);
+            ours.write(tail);
+            std.write(&bytes);
+            assert_eq!(ours.finish(), std.finish(), "{} bytes", len);
+        }
+        assert_ne!(
+            KeyedState::with_seed(1).hash_one("key"),
+            KeyedState::with_seed(2).hash_one("key")
+        );
+
+        let mut rht = RobinHoodHashTable::with_hasher(KeyedState::new());
+        rht.extend((0..300u32).map(|key| (key, key.to_string())));
+        let mut bytes = Vec::new();
+        rht.write_snapshot(&mut bytes).unwrap();
+        let loaded: RobinHoodHashTable<KeyValuePair<u32, String>, KeyedState> =
+            RobinHoodHashTable::read_snapshot(&bytes[..]).unwrap();
+        assert_eq!(loaded.hasher().seed(), rht.hasher().seed());
+        assert_eq!(loaded, rht);
+        assert!(!format!("{:?}", rht.hasher()).contains(&rht.hasher().seed().to_string()));
+    }
+
+    #[test]
+    fn colliding_keys_past_the_stored_psl_range() {
+        // Every key hashes to slot 0, so the run outgrows the PSLs the metadata
+        // can store and the tail is recomputed from cached hashes. Inserting
+        // the run one key at a time is quadratic, so it is loaded from a
+        // snapshot built by hand instead, with enough slots that the probe
+        // limit does not regrow the run either.
+        let count = u64::from(u16::MAX) + 64;
+        let bytes = colliding_snapshot(1 << 18, count, 0.9);
+        let mut rht: RobinHoodHashTable<KeyValuePair<u64, u64>, SeededState> =
+            RobinHoodHashTable::read_snapshot(&bytes[..]).unwrap();
+        rht.debug_assert_invariants();
+        assert_eq!(rht.stats().max_probe_length, count as usize - 1);
+        for key in count..count + 4 {
+            rht.insert(key, key);
+        }
+        let count = count + 4;
+        assert_eq!(rht.len(), count as usize);
+        assert!(rht.probe_limit_hits() > 0);
+        assert_eq!(rht.stats().max_probe_length, count as usize - 1);
+        for key in (0..count).step_by(997).chain(count - 8..count) {
+            assert_eq!(rht.get(&key), Some(&key));
+        }
+        assert!(!rht.contains(&count));
+        for key in count - 100..count - 50 {
+            assert_eq!(rht.remove(&key), Some(key));
+        }
+        rht.debug_assert_invariants();
+        assert_eq!(rht.get(&(count - 1)), Some(&(count - 1)));
     }
 }